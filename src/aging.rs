@@ -0,0 +1,193 @@
+use bevy::prelude::*;
+
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::main_controller::MainController;
+use crate::share_control::ControlMode;
+use crate::sliderplugin::SliderWrapper;
+use crate::{PrioritiesContainer, ProgramState};
+
+const LOWEST_PRIORITY: i32 = -2;
+const HIGHEST_PRIORITY: i32 = 2;
+
+const BOOST_MARKER_COLOR: Color = Color::srgba(0.95, 0.6, 0.3, 0.8);
+
+/// Policy on top of the sliders: a group that made no progress for a while gets its priority boosted,
+/// and the boost decays back to the slider value one level at a time, like Windows dynamic priority boosts
+pub struct AgingPlugin;
+impl Plugin for AgingPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Aging>()
+            .add_systems(Update, (spawn_boost_markers, run_aging, update_boost_markers).chain().run_if(in_state(ProgramState::Running)));
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AgingSettings {
+    pub enabled: bool,
+    /// Time without progress after which a group gets boosted
+    pub interval: Duration,
+    /// Levels a starving group gets boosted by
+    pub boost: i32,
+    /// Time each level of the boost lasts once the group makes progress again
+    pub decay: Duration,
+}
+
+impl Default for AgingSettings {
+    fn default() -> AgingSettings {
+        AgingSettings {
+            enabled: false,
+            interval: Duration::from_secs(1),
+            boost: 2,
+            decay: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Current boost of every group and how much of it the groups actually run with
+#[derive(Resource, Default)]
+pub struct Aging {
+    boosts: Vec<i32>,
+    since_decay: Vec<Duration>,
+    applied_boosts: Vec<i32>,
+    /// Why aging got turned off at runtime
+    notice: Option<String>,
+}
+
+impl Aging {
+    pub fn boost(&self, group_index: usize) -> i32 {
+        self.boosts.get(group_index).copied().unwrap_or(0)
+    }
+    /// Levels the group's applied priority sits above its slider, at most its boost
+    pub fn applied_boost(&self, group_index: usize) -> i32 {
+        self.applied_boosts.get(group_index).copied().unwrap_or(0)
+    }
+    pub fn notice(&self) -> Option<&str> {
+        self.notice.as_deref()
+    }
+
+    /// Slider priorities with the boosts added
+    fn effective_priorities(&self, priorities: &[i32]) -> Vec<i32> {
+        priorities.iter().enumerate()
+            .map(|(group_index, priority)| (priority + self.boost(group_index)).clamp(LOWEST_PRIORITY, HIGHEST_PRIORITY))
+            .collect()
+    }
+
+    /// Boosts starving groups and decays the rest
+    fn step(&mut self, main_controller: &MainController, settings: &AgingSettings, elapsed: Duration) {
+        let group_amount = main_controller.group_amount();
+        self.boosts.resize(group_amount, 0);
+        self.since_decay.resize(group_amount, Duration::ZERO);
+
+        for group_index in 0..group_amount {
+            if main_controller.is_starving(group_index, settings.interval) {
+                self.boosts[group_index] = settings.boost;
+                self.since_decay[group_index] = Duration::ZERO;
+                continue;
+            }
+            if self.boosts[group_index] == 0 { continue }
+            self.since_decay[group_index] += elapsed;
+            if self.since_decay[group_index] >= settings.decay {
+                self.boosts[group_index] -= 1;
+                self.since_decay[group_index] = Duration::ZERO;
+            }
+        }
+    }
+}
+
+/// Where a slider's boosted priority sits, hidden while the group isn't boosted
+#[derive(Component, Debug, Default, Clone)]
+struct BoostMarker;
+
+fn spawn_boost_markers(
+    mut commands: Commands,
+    sliders: Query<Entity, Added<SliderWrapper>>,
+) {
+    for slider in sliders {
+        // Added after the rail and the handle, the slider finds its handle by position
+        commands.entity(slider).with_child((
+            BoostMarker,
+            Node {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                width: Val::Px(10.),
+                height: Val::Px(30.),
+                ..default()
+            },
+            BorderRadius::MAX,
+            BackgroundColor(BOOST_MARKER_COLOR),
+        ));
+    }
+}
+
+fn run_aging(
+    time: Res<Time>,
+    mut config: ResMut<Config>,
+    control_mode: Res<ControlMode>,
+    main_controller: Res<MainController>,
+    priorities_container: Res<PrioritiesContainer>,
+    mut aging: ResMut<Aging>,
+) {
+    // The share controller sets priorities on its own
+    if *control_mode == ControlMode::TargetShares {
+        if aging.boosts.iter().any(|boost| *boost != 0) {
+            *aging = Aging { notice: aging.notice.take(), ..default() };
+        }
+        return;
+    }
+    // Turned off in the config, or below once the backend refused a boost
+    if !config.aging.enabled {
+        if aging.boosts.iter().any(|boost| *boost != 0) {
+            *aging = Aging { notice: aging.notice.take(), ..default() };
+            // Takes the boosts back
+            if let Err(error) = main_controller.update_priorities(priorities_container.priorities.clone()) {
+                error!("Couldn't apply slider priorities: {error}");
+            }
+        }
+        return;
+    }
+    aging.step(&main_controller, &config.aging, time.delta());
+
+    // Applied every frame, moving a slider or respawning the groups applies the plain slider values.
+    // The groups skip priorities they already run with
+    let effective_priorities = aging.effective_priorities(&priorities_container.priorities);
+    if let Err(error) = main_controller.update_priorities(effective_priorities.clone()) {
+        error!("Couldn't apply boosted priorities: {error}");
+    }
+
+    let applied_priorities: Vec<Option<i32>> = (0..main_controller.group_amount())
+        .map(|group_index| main_controller.applied_priority(group_index))
+        .collect();
+    aging.applied_boosts = applied_priorities.iter().zip(&priorities_container.priorities).enumerate()
+        .map(|(group_index, (applied, priority))| applied.map_or(0, |applied| (applied - priority).clamp(0, aging.boost(group_index))))
+        .collect();
+    // Raising a priority takes CAP_SYS_NICE on Linux, without it a boost never gets applied
+    let refused = applied_priorities.iter().zip(&effective_priorities).enumerate()
+        .any(|(group_index, (applied, effective))| aging.boost(group_index) > 0 && applied.is_some_and(|applied| applied < *effective));
+    if refused {
+        let notice = "Aging turned off, raising priorities takes CAP_SYS_NICE";
+        warn!("{notice}");
+        aging.notice = Some(notice.to_string());
+        config.aging.enabled = false;
+    }
+}
+
+fn update_boost_markers(
+    aging: Res<Aging>,
+    sliders: Query<(&SliderWrapper, &Children)>,
+    mut markers: Query<&mut Node, With<BoostMarker>>,
+) {
+    // Sliders are listed in group order, as when reading the priorities
+    for (group_index, (slider_wrapper, children)) in sliders.iter().enumerate() {
+        let slider = slider_wrapper.base();
+        let boost = aging.applied_boost(group_index);
+        let boosted = (slider.value + boost as f32).clamp(slider.min, slider.max);
+        for child in children {
+            let Ok(mut node) = markers.get_mut(*child) else { continue };
+            node.display = if boost > 0 && boosted != slider.value { Display::Flex } else { Display::None };
+            node.left = Val::Percent(100. * (boosted - slider.min) / (slider.max - slider.min));
+        }
+    }
+}
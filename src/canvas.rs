@@ -0,0 +1,361 @@
+use bevy::prelude::*;
+use bevy::render::{Extract, ExtractSchedule, Render, RenderApp, RenderSet};
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_resource::{
+    Extent3d, Origin3d, TexelCopyBufferLayout, TexelCopyTextureInfo, TextureAspect
+};
+use bevy::render::renderer::RenderQueue;
+use bevy::render::texture::GpuImage;
+
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU32, Ordering};
+
+use crate::main_controller::{MainImageData, BACKGROUND_COLOR};
+
+/// Side of a square tile the canvas is split into for dirty tracking
+pub const TILE_SIZE: u32 = 32;
+
+/// Uploads only the parts of the canvas that workers have touched since the last frame,
+/// instead of letting bevy re-upload the whole image
+pub struct CanvasPlugin;
+impl Plugin for CanvasPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PostUpdate, refresh_reloaded_canvas);
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else { return };
+        render_app
+            .add_systems(ExtractSchedule, extract_dirty_regions)
+            .add_systems(Render, upload_dirty_regions.in_set(RenderSet::PrepareResources));
+    }
+}
+
+/// What happens to positions that fall off the canvas
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum BoundaryMode {
+    /// Opposite edges are glued together
+    #[default]
+    Torus,
+    /// Positions are mirrored back off the wall
+    Reflect,
+    /// Nothing exists past the wall, walkers that reach it are lost
+    Absorb,
+}
+
+impl BoundaryMode {
+    pub const ALL: [BoundaryMode; 3] = [BoundaryMode::Torus, BoundaryMode::Reflect, BoundaryMode::Absorb];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BoundaryMode::Torus => "Torus",
+            BoundaryMode::Reflect => "Reflect",
+            BoundaryMode::Absorb => "Absorb",
+        }
+    }
+
+    pub fn next(&self) -> BoundaryMode {
+        BoundaryMode::ALL[(*self as usize + 1) % BoundaryMode::ALL.len()]
+    }
+
+    /// Maps a coordinate onto `0..size`, `None` if it fell off an absorbing wall
+    pub fn resolve(&self, coordinate: i32, size: i32) -> Option<i32> {
+        match self {
+            BoundaryMode::Torus => Some(coordinate.rem_euclid(size)),
+            BoundaryMode::Reflect => {
+                let mirrored = coordinate.rem_euclid(2 * size);
+                Some(if mirrored >= size { 2 * size - 1 - mirrored } else { mirrored })
+            }
+            BoundaryMode::Absorb => (0..size).contains(&coordinate).then_some(coordinate),
+        }
+    }
+}
+
+/// Pixel memory the workers draw into, one RGBA8 pixel per atomic in the canvas texture's byte order.
+/// It is owned here instead of by the image asset, so whatever bevy does to the asset
+/// (reallocating, reloading or replacing it) workers never end up writing through a dangling pointer.
+/// Workers race on pixels on purpose, relaxed loads and stores are enough since a pixel is never torn
+pub struct CanvasBuffer {
+    width: u32,
+    height: u32,
+    pixels: Box<[AtomicU32]>,
+    obstacles: Box<[AtomicBool]>,
+    dirty_tiles: DirtyTiles,
+    boundary: AtomicU8,
+}
+
+/// Color obstacle pixels are drawn with
+pub const OBSTACLE_COLOR: [u8; 4] = [110, 110, 120, 255];
+
+impl CanvasBuffer {
+    pub fn new(width: u32, height: u32, background: [u8; 4]) -> CanvasBuffer {
+        CanvasBuffer {
+            width,
+            height,
+            pixels: (0..width as usize * height as usize)
+                .map(|_| AtomicU32::new(u32::from_ne_bytes(background)))
+                .collect(),
+            obstacles: (0..width as usize * height as usize).map(|_| AtomicBool::new(false)).collect(),
+            dirty_tiles: DirtyTiles::new(width, height),
+            boundary: AtomicU8::new(BoundaryMode::default() as u8),
+        }
+    }
+    pub fn width(&self) -> u32 {self.width}
+    pub fn height(&self) -> u32 {self.height}
+    pub fn dirty_tiles(&self) -> &DirtyTiles {&self.dirty_tiles}
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        self.pixels[(x + y * self.width) as usize].load(Ordering::Relaxed).to_ne_bytes()
+    }
+    /// Doesn't check for obstacles or mark the pixel's tile dirty
+    pub fn set_pixel(&self, x: u32, y: u32, color: [u8; 4]) {
+        self.pixels[(x + y * self.width) as usize].store(u32::from_ne_bytes(color), Ordering::Relaxed);
+    }
+    /// Can be changed while workers are running, they pick it up on their next step
+    pub fn boundary(&self) -> BoundaryMode {
+        BoundaryMode::ALL[self.boundary.load(Ordering::Relaxed) as usize]
+    }
+    pub fn set_boundary(&self, boundary: BoundaryMode) {
+        self.boundary.store(boundary as u8, Ordering::Relaxed);
+    }
+    /// Walkers can't pass obstacles and nothing can be drawn over them
+    pub fn is_obstacle(&self, x: u32, y: u32) -> bool {
+        self.obstacles[(x + y * self.width) as usize].load(Ordering::Relaxed)
+    }
+    /// Places or removes an obstacle, repainting the pixel with the obstacle or background color
+    pub fn set_obstacle(&self, x: u32, y: u32, obstacle: bool) {
+        let index = (x + y * self.width) as usize;
+        if self.obstacles[index].swap(obstacle, Ordering::Relaxed) == obstacle { return }
+
+        let color = if obstacle { OBSTACLE_COLOR } else { BACKGROUND_COLOR };
+        self.set_pixel(x, y, color);
+        self.dirty_tiles.mark(x, y);
+    }
+    /// Number of pixels of each of the given colors
+    pub fn count_colors(&self, colors: &[[u8; 4]]) -> Vec<u64> {
+        let mut counts = vec![0; colors.len()];
+        for pixel in &self.pixels {
+            let pixel = pixel.load(Ordering::Relaxed).to_ne_bytes();
+            if let Some(color_index) = colors.iter().position(|color| *color == pixel) {
+                counts[color_index] += 1;
+            }
+        }
+        counts
+    }
+    /// Pixels that aren't obstacles
+    pub fn free_pixels(&self) -> u64 {
+        self.obstacles.iter().filter(|obstacle| !obstacle.load(Ordering::Relaxed)).count() as u64
+    }
+    /// Paints everything but the obstacles with the background color.
+    /// Workers must not be drawing while this runs, or their pixels may survive it
+    pub fn clear(&self) {
+        let background = u32::from_ne_bytes(BACKGROUND_COLOR);
+        for (pixel, obstacle) in self.pixels.iter().zip(&self.obstacles) {
+            if obstacle.load(Ordering::Relaxed) { continue }
+            pixel.store(background, Ordering::Relaxed);
+        }
+        self.dirty_tiles.mark_all();
+    }
+    /// Appends `width` pixels starting at the given one to `bytes`, in the texture's layout
+    fn copy_line(&self, x: u32, y: u32, width: u32, bytes: &mut Vec<u8>) {
+        let start = (x + y * self.width) as usize;
+        for pixel in &self.pixels[start..start + width as usize] {
+            bytes.extend(pixel.load(Ordering::Relaxed).to_ne_bytes());
+        }
+    }
+}
+
+/// Per-tile dirty flags, shared between the workers that mark them and the render world that clears them
+pub struct DirtyTiles {
+    tiles: Box<[AtomicBool]>,
+    any: AtomicBool,
+    columns: u32,
+    rows: u32,
+}
+
+impl DirtyTiles {
+    pub fn new(width: u32, height: u32) -> DirtyTiles {
+        let columns = width.div_ceil(TILE_SIZE);
+        let rows = height.div_ceil(TILE_SIZE);
+        DirtyTiles {
+            tiles: (0..columns * rows).map(|_| AtomicBool::new(false)).collect(),
+            any: AtomicBool::new(false),
+            columns,
+            rows,
+        }
+    }
+
+    pub fn mark_all(&self) {
+        self.tiles.iter().for_each(|tile| tile.store(true, Ordering::Relaxed));
+        self.any.store(true, Ordering::Relaxed);
+    }
+
+    pub fn mark(&self, x: u32, y: u32) {
+        let index = (x / TILE_SIZE + (y / TILE_SIZE) * self.columns) as usize;
+        self.tiles[index].store(true, Ordering::Relaxed);
+        self.any.store(true, Ordering::Relaxed);
+    }
+
+    /// Clears every flag and returns the dirty tiles merged into horizontal runs,
+    /// as `(first_column, row, column_count)` in tile units
+    pub fn take_runs(&self) -> Vec<(u32, u32, u32)> {
+        let mut runs = Vec::new();
+        if !self.any.swap(false, Ordering::Relaxed) { return runs }
+
+        for row in 0..self.rows {
+            let mut run_start: Option<u32> = None;
+            for column in 0..=self.columns {
+                let dirty = column < self.columns &&
+                    self.tiles[(column + row * self.columns) as usize].swap(false, Ordering::Relaxed);
+
+                match (dirty, run_start) {
+                    (true, None) => run_start = Some(column),
+                    (false, Some(start)) => {
+                        runs.push((start, row, column - start));
+                        run_start = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        runs
+    }
+}
+
+/// A rectangle of the canvas copied out of the shared pixel buffer, in pixels
+struct DirtyRegion {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    bytes: Vec<u8>,
+}
+
+#[derive(Resource, Default)]
+struct CanvasUpload {
+    image: AssetId<Image>,
+    regions: Vec<DirtyRegion>,
+}
+
+/// Whenever bevy re-uploads the image asset from its own (stale) data, the texture has to be
+/// refreshed from the canvas buffer
+fn refresh_reloaded_canvas(
+    main_image_data: Option<Res<MainImageData>>,
+    mut asset_events: EventReader<AssetEvent<Image>>,
+) {
+    let Some(main_image_data) = main_image_data else { return };
+    let canvas_id = main_image_data.handle().id();
+
+    for event in asset_events.read() {
+        match event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } | AssetEvent::LoadedWithDependencies { id }
+                if *id == canvas_id => main_image_data.canvas().dirty_tiles().mark_all(),
+            _ => {}
+        }
+    }
+}
+
+fn extract_dirty_regions(
+    mut commands: Commands,
+    main_image_data: Extract<Option<Res<MainImageData>>>,
+) {
+    let Some(main_image_data) = main_image_data.as_ref() else { return };
+    let canvas = main_image_data.canvas();
+
+    let canvas_width = canvas.width();
+    let canvas_height = canvas.height();
+
+    let regions = canvas.dirty_tiles().take_runs().into_iter().map(|(column, row, columns)| {
+        let x = column * TILE_SIZE;
+        let y = row * TILE_SIZE;
+        let width = (columns * TILE_SIZE).min(canvas_width - x);
+        let height = TILE_SIZE.min(canvas_height - y);
+
+        let mut bytes = Vec::with_capacity((4 * width * height) as usize);
+        // Workers keep writing while we copy, a pixel they change afterwards is picked up again next frame
+        for line in y..y + height {
+            canvas.copy_line(x, line, width, &mut bytes);
+        }
+        DirtyRegion { x, y, width, height, bytes }
+    }).collect();
+
+    commands.insert_resource(CanvasUpload { image: main_image_data.handle().id(), regions });
+}
+
+fn upload_dirty_regions(
+    upload: Option<Res<CanvasUpload>>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    render_queue: Res<RenderQueue>,
+) {
+    let Some(upload) = upload else { return };
+    let Some(gpu_image) = gpu_images.get(upload.image) else { return };
+
+    for region in &upload.regions {
+        // The texture may still have the old size for a frame after the canvas was resized
+        if region.x + region.width > gpu_image.size.width || region.y + region.height > gpu_image.size.height {
+            continue;
+        }
+        render_queue.write_texture(
+            TexelCopyTextureInfo {
+                texture: &gpu_image.texture,
+                mip_level: 0,
+                origin: Origin3d { x: region.x, y: region.y, z: 0 },
+                aspect: TextureAspect::All,
+            },
+            &region.bytes,
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * region.width),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: region.width,
+                height: region.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dirty_tiles_merge_into_runs() {
+        // 4 columns and 2 rows of tiles, the last ones partial
+        let tiles = DirtyTiles::new(100, 40);
+        tiles.mark(0, 0);
+        tiles.mark(40, 10);
+        tiles.mark(99, 0);
+        tiles.mark(33, 33);
+        assert_eq!(tiles.take_runs(), vec![(0, 0, 2), (3, 0, 1), (1, 1, 1)]);
+        assert!(tiles.take_runs().is_empty());
+    }
+
+    #[test]
+    fn all_dirty_tiles_are_one_run_per_row() {
+        let tiles = DirtyTiles::new(100, 40);
+        tiles.mark_all();
+        assert_eq!(tiles.take_runs(), vec![(0, 0, 4), (0, 1, 4)]);
+    }
+
+    #[test]
+    fn torus_wraps_around() {
+        assert_eq!(BoundaryMode::Torus.resolve(-1, 10), Some(9));
+        assert_eq!(BoundaryMode::Torus.resolve(10, 10), Some(0));
+        assert_eq!(BoundaryMode::Torus.resolve(25, 10), Some(5));
+    }
+
+    #[test]
+    fn reflect_mirrors_off_the_wall() {
+        assert_eq!(BoundaryMode::Reflect.resolve(-1, 10), Some(0));
+        assert_eq!(BoundaryMode::Reflect.resolve(10, 10), Some(9));
+        assert_eq!(BoundaryMode::Reflect.resolve(12, 10), Some(7));
+        assert_eq!(BoundaryMode::Reflect.resolve(20, 10), Some(0));
+    }
+
+    #[test]
+    fn absorb_loses_what_falls_off() {
+        assert_eq!(BoundaryMode::Absorb.resolve(0, 10), Some(0));
+        assert_eq!(BoundaryMode::Absorb.resolve(9, 10), Some(9));
+        assert_eq!(BoundaryMode::Absorb.resolve(-1, 10), None);
+        assert_eq!(BoundaryMode::Absorb.resolve(10, 10), None);
+    }
+}
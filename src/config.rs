@@ -0,0 +1,384 @@
+use bevy::prelude::*;
+
+use std::env;
+use std::fs;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::aging::AgingSettings;
+use crate::canvas::BoundaryMode;
+use crate::main_controller::{RateLimit, RateUnit};
+use crate::race::EndConditions;
+use crate::sweep::SweepSettings;
+use crate::workload::{Fractal, LaunchMode, Neighborhood, SeedPosition, SeedShape, WorkloadSettings};
+use crate::GROUP_AMOUNT;
+
+/// File in the working directory read on startup, one `key = value` per line
+const CONFIG_PATH: &str = "prioritything.cfg";
+
+/// Resolutions the resize button cycles through
+pub const RESOLUTION_PRESETS: [(u32, u32); 5] = [
+    (250, 250),
+    (500, 500),
+    (1000, 1000),
+    (1600, 900),
+    (2000, 2000),
+];
+
+/// Startup configuration. Values from the config file are overridden by
+/// command line arguments of the form `--key value` or `--key=value`.
+/// Workload keys can be set for a single group by prefixing them, e.g. `g1.dla_sticking = 0.2`
+#[derive(Resource, Clone, Debug)]
+pub struct Config {
+    pub canvas_width: u32,
+    pub canvas_height: u32,
+    pub boundary: BoundaryMode,
+    /// Workload parameters every group starts from
+    pub workload: WorkloadSettings,
+    /// Image in the assets folder whose dark pixels become obstacles, stretched over the canvas
+    pub obstacle_mask: Option<String>,
+    /// Whether resetting the canvas keeps the group colors or rolls new ones
+    pub keep_colors: bool,
+    /// When a race is over, none by default
+    pub end_conditions: EndConditions,
+    /// Runs in a tournament
+    pub tournament_runs: u32,
+    /// A running group that makes no progress for this long is flagged as starving
+    pub starvation_threshold: Duration,
+    /// Priority boosts for starving groups, off by default
+    pub aging: AgingSettings,
+    /// Set to run a headless priority sweep instead of the app
+    pub sweep: SweepSettings,
+    group_overrides: Vec<(usize, String, String)>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            canvas_width: 1000,
+            canvas_height: 1000,
+            boundary: BoundaryMode::default(),
+            workload: WorkloadSettings::default(),
+            obstacle_mask: None,
+            keep_colors: true,
+            end_conditions: EndConditions::default(),
+            tournament_runs: 10,
+            starvation_threshold: Duration::from_secs(2),
+            aging: AgingSettings::default(),
+            sweep: SweepSettings::default(),
+            group_overrides: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Config {
+        let mut config = Config::default();
+
+        if let Ok(file) = fs::read_to_string(CONFIG_PATH) {
+            for line in file.lines().map(str::trim) {
+                if line.is_empty() || line.starts_with('#') { continue }
+                match line.split_once('=') {
+                    Some((key, value)) => config.set(key.trim(), value.trim()),
+                    None => eprintln!("Ignoring malformed line in {CONFIG_PATH}: {line}"),
+                }
+            }
+        }
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let Some(arg) = arg.strip_prefix("--") else {
+                eprintln!("Ignoring unexpected argument {arg}");
+                continue;
+            };
+            match arg.split_once('=') {
+                Some((key, value)) => config.set(key, value),
+                None => config.set(arg, &args.next().unwrap_or_default()),
+            }
+        }
+
+        if config.canvas_width == 0 || config.canvas_height == 0 {
+            eprintln!("Canvas can't be empty, falling back to the default resolution");
+            let default = Config::default();
+            (config.canvas_width, config.canvas_height) = (default.canvas_width, default.canvas_height);
+        }
+        config
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        let parsed = match key {
+            "width" => Some(parse_into(&mut self.canvas_width, value)),
+            "height" => Some(parse_into(&mut self.canvas_height, value)),
+            "size" => Some(match value.split_once('x') {
+                Some((width, height)) => parse_into(&mut self.canvas_width, width)
+                    && parse_into(&mut self.canvas_height, height),
+                None => false,
+            }),
+            "boundary" => Some(match BoundaryMode::ALL.iter().find(|mode| mode.name().eq_ignore_ascii_case(value)) {
+                Some(mode) => { self.boundary = *mode; true }
+                None => false,
+            }),
+            "reset_colors" => Some(match value {
+                "keep" => { self.keep_colors = true; true }
+                "new" => { self.keep_colors = false; true }
+                _ => false,
+            }),
+            "end_fill" => Some(match value.parse::<f64>() {
+                Ok(percent) if (0. ..=100.).contains(&percent) => { self.end_conditions.fill_percent = Some(percent); true }
+                _ => false,
+            }),
+            "end_pixels" => Some(match value.parse() {
+                Ok(pixels) => { self.end_conditions.group_pixels = Some(pixels); true }
+                Err(_) => false,
+            }),
+            "end_edge" => Some(parse_into(&mut self.end_conditions.touch_edge, value)),
+            "end_time_s" => Some(match value.parse::<f64>() {
+                Ok(seconds) if seconds > 0. => { self.end_conditions.time_limit = Some(Duration::from_secs_f64(seconds)); true }
+                _ => false,
+            }),
+            "tournament_runs" => Some(match value.parse() {
+                Ok(runs) if runs > 0 => { self.tournament_runs = runs; true }
+                _ => false,
+            }),
+            "starvation_ms" => Some(match value.parse() {
+                Ok(threshold_ms) => { self.starvation_threshold = Duration::from_millis(threshold_ms); true }
+                Err(_) => false,
+            }),
+            "aging" => Some(parse_into(&mut self.aging.enabled, value)),
+            "aging_interval_ms" => Some(match value.parse() {
+                Ok(interval_ms) => { self.aging.interval = Duration::from_millis(interval_ms); true }
+                Err(_) => false,
+            }),
+            "aging_boost" => Some(match value.parse() {
+                Ok(boost) if boost > 0 => { self.aging.boost = boost; true }
+                _ => false,
+            }),
+            "aging_decay_ms" => Some(match value.parse() {
+                Ok(decay_ms) => { self.aging.decay = Duration::from_millis(decay_ms); true }
+                Err(_) => false,
+            }),
+            "sweep" => Some(parse_into(&mut self.sweep.enabled, value)),
+            "sweep_duration_s" => Some(match value.parse::<f64>() {
+                Ok(seconds) if seconds > 0. => { self.sweep.duration = Duration::from_secs_f64(seconds); true }
+                _ => false,
+            }),
+            "sweep_range" => Some(match value.split_once("..") {
+                Some((low, high)) => match (low.trim().parse(), high.trim().parse()) {
+                    (Ok(low), Ok(high)) if low <= high => { self.sweep.range = (low, high); true }
+                    _ => false,
+                },
+                None => false,
+            }),
+            "sweep_output" => { self.sweep.output = value.to_string(); Some(!value.is_empty()) }
+            "obstacle_mask" => { self.obstacle_mask = (!value.is_empty()).then(|| value.to_string()); Some(true) }
+            _ => match group_key(key) {
+                Some((group_index, workload_key)) => {
+                    // Validated right away, applied once the groups get created
+                    let parsed = set_workload_key(&mut WorkloadSettings::default(), workload_key, value);
+                    if parsed == Some(true) {
+                        self.group_overrides.push((group_index, workload_key.to_string(), value.to_string()));
+                    }
+                    parsed
+                }
+                None => set_workload_key(&mut self.workload, key, value),
+            }
+        };
+        match parsed {
+            Some(true) => {}
+            Some(false) => eprintln!("Invalid value {value:?} for {key}"),
+            None => eprintln!("Unknown config key {key}"),
+        }
+    }
+
+    /// Workload parameters of every group, with the group's overrides applied
+    pub fn group_settings(&self, group_amount: u32) -> Vec<WorkloadSettings> {
+        (0..group_amount as usize).map(|group_index| {
+            let mut settings = self.workload.clone();
+            self.group_overrides.iter()
+                .filter(|(override_group, _, _)| *override_group == group_index)
+                .for_each(|(_, key, value)| {set_workload_key(&mut settings, key, value);});
+            if settings.seed.position == SeedPosition::Symmetric {
+                settings.seed.position = SeedPosition::symmetric(group_index, group_amount as usize);
+            }
+            settings
+        }).collect()
+    }
+
+    /// The preset following the current canvas resolution
+    pub fn next_resolution(&self) -> (u32, u32) {
+        let current = (self.canvas_width, self.canvas_height);
+        match RESOLUTION_PRESETS.iter().position(|preset| *preset == current) {
+            Some(index) => RESOLUTION_PRESETS[(index + 1) % RESOLUTION_PRESETS.len()],
+            None => RESOLUTION_PRESETS[0],
+        }
+    }
+}
+
+fn parse_into<T: FromStr>(target: &mut T, value: &str) -> bool {
+    match value.trim().parse() {
+        Ok(value) => {
+            *target = value;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Splits a key like `g1.dla_sticking` into the group index and the workload key.
+/// Keys of groups that don't exist are unknown
+fn group_key(key: &str) -> Option<(usize, &str)> {
+    let (group, key) = key.strip_prefix('g')?.split_once('.')?;
+    let group_index = group.parse().ok().filter(|group_index| *group_index < GROUP_AMOUNT as usize)?;
+    Some((group_index, key))
+}
+
+/// Returns `None` for unknown keys and `Some(false)` for values that don't parse
+fn set_workload_key(settings: &mut WorkloadSettings, key: &str, value: &str) -> Option<bool> {
+    let parsed = match key {
+        "interactive_period_ms" => match value.parse() {
+            Ok(period_ms) => { settings.interactive_period = Duration::from_millis(period_ms); true }
+            Err(_) => false,
+        },
+        "dla_sticking" => match value.parse::<f64>() {
+            Ok(probability) if (0. ..=1.).contains(&probability) => { settings.dla.sticking_probability = probability; true }
+            _ => false,
+        },
+        "dla_neighborhood" => match value {
+            "4" => { settings.dla.neighborhood = Neighborhood::Four; true }
+            "8" => { settings.dla.neighborhood = Neighborhood::Eight; true }
+            _ => false,
+        },
+        "dla_launch" => match value {
+            "uniform" => { settings.dla.launch = LaunchMode::Uniform; true }
+            "ring" => { settings.dla.launch = LaunchMode::Ring; true }
+            "edge" => { settings.dla.launch = LaunchMode::Edge; true }
+            _ => false,
+        },
+        "dla_steps" => parse_into(&mut settings.dla.step_budget, value),
+        "rate_limit" => match parse_rate_limit(value) {
+            Some(limit) => { settings.rate_limit = limit; true }
+            None => false,
+        },
+        "pipeline_capacity" => match value.parse() {
+            Ok(capacity) if capacity > 0 => { settings.pipeline.capacity = capacity; true }
+            _ => false,
+        },
+        "tile_fractal" => match value {
+            "mandelbrot" => { settings.tiles.fractal = Fractal::Mandelbrot; true }
+            "julia" => { settings.tiles.fractal = Fractal::Julia; true }
+            _ => false,
+        },
+        "seed_shape" => match parse_seed_shape(value) {
+            Some(shape) => { settings.seed.shape = shape; true }
+            None => false,
+        },
+        "seed_position" => match parse_seed_position(value) {
+            Some(position) => { settings.seed.position = position; true }
+            None => false,
+        },
+        _ => return None,
+    };
+    Some(parsed)
+}
+
+/// `off`, or a rate per second optionally followed by `walks` or `progress`, e.g. `200 progress`
+fn parse_rate_limit(value: &str) -> Option<Option<RateLimit>> {
+    if value == "off" { return Some(None) }
+    let (per_second, unit) = match value.split_once(' ') {
+        Some((per_second, unit)) => (per_second, unit.trim()),
+        None => (value, "walks"),
+    };
+    let per_second: f64 = per_second.parse().ok().filter(|per_second| *per_second > 0.)?;
+    let unit = match unit {
+        "walks" => RateUnit::Walks,
+        "progress" => RateUnit::Progress,
+        _ => return None,
+    };
+    Some(Some(RateLimit { per_second, unit }))
+}
+
+/// `point`, `points:<amount>`, `line:<length>` or `circle:<radius>`
+fn parse_seed_shape(value: &str) -> Option<SeedShape> {
+    let (name, size) = match value.split_once(':') {
+        Some((name, size)) => (name, Some(size.trim().parse().ok()?)),
+        None => (value, None),
+    };
+    match (name.trim(), size) {
+        ("point", None) => Some(SeedShape::Point),
+        ("points", Some(amount)) => Some(SeedShape::Points(amount)),
+        ("line", Some(length)) => Some(SeedShape::Line(length)),
+        ("circle", Some(radius)) => Some(SeedShape::Circle(radius)),
+        _ => None,
+    }
+}
+
+/// `random`, `symmetric` or `<x>,<y>` as fractions of the canvas size
+fn parse_seed_position(value: &str) -> Option<SeedPosition> {
+    match value {
+        "random" => Some(SeedPosition::Random),
+        "symmetric" => Some(SeedPosition::Symmetric),
+        _ => {
+            let (x, y) = value.split_once(',')?;
+            let fraction = [x.trim().parse().ok()?, y.trim().parse().ok()?];
+            fraction.iter().all(|coordinate| (0. ..=1.).contains(coordinate)).then_some(SeedPosition::At(fraction))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_values() {
+        let mut config = Config::default();
+        config.set("size", "800x600");
+        config.set("boundary", "absorb");
+        config.set("sweep_range", "-1..2");
+        config.set("rate_limit", "200 progress");
+        assert_eq!((config.canvas_width, config.canvas_height), (800, 600));
+        assert_eq!(config.boundary, BoundaryMode::Absorb);
+        assert_eq!(config.sweep.range, (-1, 2));
+        let limit = config.workload.rate_limit.unwrap();
+        assert_eq!((limit.per_second, limit.unit), (200., RateUnit::Progress));
+    }
+
+    #[test]
+    fn malformed_values_keep_the_defaults() {
+        let mut config = Config::default();
+        let default = Config::default();
+        config.set("width", "wide");
+        config.set("size", "800");
+        config.set("boundary", "bouncy");
+        config.set("end_fill", "150");
+        config.set("sweep_range", "3..1");
+        config.set("rate_limit", "-5");
+        config.set("dla_launch", "sideways");
+        config.set("no_such_key", "1");
+        assert_eq!((config.canvas_width, config.canvas_height), (default.canvas_width, default.canvas_height));
+        assert_eq!(config.boundary, default.boundary);
+        assert_eq!(config.end_conditions.fill_percent, None);
+        assert_eq!(config.sweep.range, default.sweep.range);
+        assert!(config.workload.rate_limit.is_none());
+        assert_eq!(config.workload.dla.launch, default.workload.dla.launch);
+    }
+
+    #[test]
+    fn group_overrides_apply_to_their_group() {
+        let mut config = Config::default();
+        config.set("g1.dla_sticking", "0.5");
+        config.set("g2.dla_sticking", "2");
+        let settings = config.group_settings(GROUP_AMOUNT);
+        assert_eq!(settings[0].dla.sticking_probability, 1.);
+        assert_eq!(settings[1].dla.sticking_probability, 0.5);
+        assert_eq!(settings[2].dla.sticking_probability, 1.);
+    }
+
+    #[test]
+    fn group_keys_need_an_existing_group() {
+        assert_eq!(group_key("g1.dla_steps"), Some((1, "dla_steps")));
+        assert_eq!(group_key(&format!("g{GROUP_AMOUNT}.dla_steps")), None);
+        assert_eq!(group_key("gx.dla_steps"), None);
+        assert_eq!(group_key("dla_steps"), None);
+    }
+}
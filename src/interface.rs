@@ -1,8 +1,19 @@
 use bevy::prelude::*;
 use std::thread;
 
+use crate::config::Config;
+use crate::share_control;
 use crate::sliderplugin;
+use crate::stats;
+use crate::tools;
 use crate::main_controller;
+use crate::workload::{self, WorkloadKind};
+use crate::GROUP_AMOUNT;
+
+pub const FONT_PATH: &str = "Inter-Black.ttf";
+
+/// Query filter for the buttons marked with `T` whose interaction changed
+pub type ButtonInteraction<T> = (Changed<Interaction>, With<T>);
 
 #[derive(Component, Debug, Default, Clone)]
 pub struct StartButton;
@@ -10,6 +21,79 @@ pub struct StartButton;
 #[derive(Component, Debug, Default, Clone)]
 pub struct StopButton;
 
+#[derive(Component, Debug, Default, Clone)]
+pub struct ResizeButton;
+
+#[derive(Component, Debug, Default, Clone)]
+pub struct ResolutionLabel;
+
+#[derive(Component, Debug, Default, Clone)]
+pub struct BoundaryButton;
+
+#[derive(Component, Debug, Default, Clone)]
+pub struct BoundaryLabel;
+
+/// Clears the canvas and respawns every group, see [`main_controller::MainController::reset`]
+#[derive(Component, Debug, Default, Clone)]
+pub struct ResetButton;
+
+/// Toggles whether a reset keeps the group colors
+#[derive(Component, Debug, Default, Clone)]
+pub struct ResetColorsButton;
+
+#[derive(Component, Debug, Default, Clone)]
+pub struct ResetColorsLabel;
+
+/// Starts or cancels a tournament, see [`crate::tournament::Tournament`]
+#[derive(Component, Debug, Default, Clone)]
+pub struct TournamentButton;
+
+#[derive(Component, Debug, Default, Clone)]
+pub struct TournamentLabel;
+
+/// Switches what the group sliders set, see [`crate::share_control::ControlMode`]
+#[derive(Component, Debug, Default, Clone)]
+pub struct ControlModeButton;
+
+#[derive(Component, Debug, Default, Clone)]
+pub struct ControlModeLabel;
+
+/// Sets the workloads and sliders of every group to the preset with this index, see [`crate::PRESETS`]
+#[derive(Component, Debug, Default, Clone)]
+pub struct PresetButton(pub usize);
+
+/// Switches the lock the lock workloads share, see [`crate::workload::LockKind`]
+#[derive(Component, Debug, Default, Clone)]
+pub struct LockKindButton;
+
+#[derive(Component, Debug, Default, Clone)]
+pub struct LockKindLabel;
+
+/// Cycles what clicking on the canvas does, see [`tools::ToolMode`]
+#[derive(Component, Debug, Default, Clone)]
+pub struct ToolButton;
+
+#[derive(Component, Debug, Default, Clone)]
+pub struct ToolLabel;
+
+/// Cycles the workload of the group with this index
+#[derive(Component, Debug, Default, Clone)]
+pub struct WorkloadButton(pub usize);
+
+#[derive(Component, Debug, Default, Clone)]
+pub struct WorkloadLabel(pub usize);
+
+/// Cycles the token bucket limit of the group with this index
+#[derive(Component, Debug, Default, Clone)]
+pub struct RateLimitButton(pub usize);
+
+#[derive(Component, Debug, Default, Clone)]
+pub struct RateLimitLabel(pub usize);
+
+/// Node displaying the canvas image, keeps the aspect ratio of the canvas
+#[derive(Component, Debug, Default, Clone)]
+pub struct CanvasNode;
+
 pub const START_BUTTON_IDLE_COLOR: Color = Color::srgb(0.20, 0.35, 0.25);
 pub const START_BUTTON_PRESSED_COLOR: Color = Color::srgb(0.20*0.5, 0.35*0.5, 0.25*0.5);
 pub const START_BUTTON_HOVERED: Color = Color::srgb(0.20, 0.35*2., 0.25);
@@ -18,10 +102,58 @@ pub const STOP_BUTTON_IDLE_COLOR: Color = Color::srgb(0.35, 0.20, 0.20);
 pub const STOP_BUTTON_PRESSED_COLOR: Color = Color::srgb(0.35*0.5, 0.20*0.5, 0.20*0.5);
 pub const STOP_BUTTON_HOVERED: Color = Color::srgb(0.35*2., 0.20, 0.20);
 
+pub const TOOL_BUTTON_IDLE_COLOR: Color = Color::srgb(0.22, 0.22, 0.25);
+pub const TOOL_BUTTON_PRESSED_COLOR: Color = Color::srgb(0.22*0.5, 0.22*0.5, 0.25*0.5);
+pub const TOOL_BUTTON_HOVERED: Color = Color::srgb(0.22*1.5, 0.22*1.5, 0.25*1.5);
+
+/// Small button for the options header, `label` is the text bundle shown on it
+fn tool_button(marker: impl Component, label: impl Bundle, font: Handle<Font>) -> impl Bundle {
+    (
+        marker,
+        Button,
+        Node {
+            height: Val::Percent(70.0),
+            margin: UiRect::horizontal(Val::Px(10.)),
+            padding: UiRect::horizontal(Val::Px(10.)),
+
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        BorderRadius::all(Val::Px(8.)),
+        BackgroundColor(TOOL_BUTTON_IDLE_COLOR),
+        children![(
+            label,
+            TextFont {
+                font,
+                font_size: 20.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.9, 0.9, 0.9)),
+        )]
+    )
+}
+
+pub fn reset_colors_text(keep_colors: bool) -> &'static str {
+    if keep_colors { "Keep colors" } else { "New colors" }
+}
+
+pub fn rate_limit_text(limit: Option<main_controller::RateLimit>) -> String {
+    match limit {
+        Some(limit) => format!("{} {}/s", stats::format_si(limit.per_second), limit.unit.name()),
+        None => "No limit".to_string(),
+    }
+}
+
+pub fn resolution_text(width: u32, height: u32) -> String {
+    format!("{}x{}", width, height)
+}
+
 pub fn setup_ui(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    main_image_data: Res<main_controller::MainImageData>
+    main_image_data: Res<main_controller::MainImageData>,
+    config: Res<Config>,
 ) {
     let start_button = (
         StartButton,
@@ -40,7 +172,7 @@ pub fn setup_ui(
         children![(
             Text::new("Start"),
             TextFont {
-                font: asset_server.load("Inter-Black.ttf"),
+                font: asset_server.load(FONT_PATH),
                 font_size: 33.0,
                 ..default()
             },
@@ -65,7 +197,31 @@ pub fn setup_ui(
         children![(
             Text::new("Stop"),
             TextFont {
-                font: asset_server.load("Inter-Black.ttf"),
+                font: asset_server.load(FONT_PATH),
+                font_size: 33.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.9, 0.9, 0.9)),
+        )]
+    );
+    let reset_button = (
+        ResetButton,
+        Button,
+        Node {
+            width: Val::Percent(50.0),
+            height: Val::Percent(50.0),
+            margin: UiRect::all(Val::Percent(5.)),
+
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        BorderRadius::percent(20., 20., 20., 20.),
+        BackgroundColor(TOOL_BUTTON_IDLE_COLOR),
+        children![(
+            Text::new("Reset"),
+            TextFont {
+                font: asset_server.load(FONT_PATH),
                 font_size: 33.0,
                 ..default()
             },
@@ -84,18 +240,67 @@ pub fn setup_ui(
         },
         children![
             start_button,
-            stop_button
+            stop_button,
+            reset_button
         ]
     );
+    let resize_button = tool_button(
+        ResizeButton,
+        (ResolutionLabel, Text::new(resolution_text(config.canvas_width, config.canvas_height))),
+        asset_server.load(FONT_PATH),
+    );
+    let boundary_button = tool_button(
+        BoundaryButton,
+        (BoundaryLabel, Text::new(config.boundary.name())),
+        asset_server.load(FONT_PATH),
+    );
+    let tool_mode_button = tool_button(
+        ToolButton,
+        (ToolLabel, Text::new(tools::ToolMode::default().name())),
+        asset_server.load(FONT_PATH),
+    );
+    let reset_colors_button = tool_button(
+        ResetColorsButton,
+        (ResetColorsLabel, Text::new(reset_colors_text(config.keep_colors))),
+        asset_server.load(FONT_PATH),
+    );
+    let tournament_button = tool_button(
+        TournamentButton,
+        (TournamentLabel, Text::new("Tournament")),
+        asset_server.load(FONT_PATH),
+    );
+    let control_mode_button = tool_button(
+        ControlModeButton,
+        (ControlModeLabel, Text::new(share_control::ControlMode::default().name())),
+        asset_server.load(FONT_PATH),
+    );
+    let inversion_preset_button = tool_button(
+        PresetButton(0),
+        Text::new(crate::PRESETS[0].name),
+        asset_server.load(FONT_PATH),
+    );
+    let pipeline_preset_button = tool_button(
+        PresetButton(1),
+        Text::new(crate::PRESETS[1].name),
+        asset_server.load(FONT_PATH),
+    );
+    let lock_kind_button = tool_button(
+        LockKindButton,
+        (LockKindLabel, Text::new(workload::lock_kind().name())),
+        asset_server.load(FONT_PATH),
+    );
     let options_header_frame = (
         Node {
             width: Val::Percent(100.0),
             height: Val::Px(60.0),
             bottom: Val::Px(5.0),
 
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
             ..default()
         },
         BackgroundColor(Color::srgb(0.16, 0.16, 0.18)),
+        children![resize_button, boundary_button, tool_mode_button, reset_colors_button, tournament_button, control_mode_button, inversion_preset_button, lock_kind_button, pipeline_preset_button]
     );
     let sliders_main_frame = (
         Node {
@@ -124,26 +329,45 @@ pub fn setup_ui(
             buttons_frame
         ]
     );
-    let screen_frame = (
+    let canvas = (
+        CanvasNode,
+        Interaction::default(),
+        bevy::ui::RelativeCursorPosition::default(),
         Node {
-            width: Val::Percent(100.),
-            height: Val::Percent(60.),
+            height: Val::Percent(100.),
+            max_width: Val::Percent(100.),
+            aspect_ratio: Some(main_image_data.width() as f32 / main_image_data.height() as f32),
             ..default()
         },
         ImageNode {
             image: main_image_data.handle(),
             ..default()
         },
+    );
+    let screen_frame = (
+        Node {
+            width: Val::Percent(100.),
+            height: Val::Percent(60.),
+
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
         BorderRadius::percent(10., 10., 10., 10.),
         BackgroundColor(Color::BLACK),
+        children![canvas]
     );
     let process_info_frame = (
         Node {
             width: Val::Percent(100.),
             height: Val::Percent(40.),
+            padding: UiRect::all(Val::Px(10.)),
+
+            flex_direction: FlexDirection::Column,
             ..default()
         },
         BorderRadius::percent(10., 10., 10., 10.),
+        stats::StatsPanel,
     );
     let showcase_frame = (
         Node {
@@ -218,15 +442,18 @@ pub struct Sliders;
 pub fn setup_sliders(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
+    config: Res<Config>,
     query: Query<(Entity, &Sliders), Added<Sliders>>,
 ) {
+    let group_settings = config.group_settings(GROUP_AMOUNT);
+
     let threads = thread::available_parallelism()
         .map(|n| n.get())
         .unwrap_or(1);
 
     for (entity, _slider) in query {
         commands.entity(entity).with_children(|slider_main| {
-            for slider_index in 0..3 {
+            for (slider_index, settings) in group_settings.iter().enumerate() {
                 slider_main.spawn((
                     Node {
                         height: Val::Px(100.),
@@ -250,7 +477,7 @@ pub fn setup_sliders(
                             children![(
                                 Text::new(format!["G{}", slider_index]),
                                 TextFont {
-                                    font: asset_server.load("Inter-Black.ttf"),
+                                    font: asset_server.load(FONT_PATH),
                                     font_size: 40.0,
                                     ..default()
                                 },
@@ -258,7 +485,55 @@ pub fn setup_sliders(
                             )]
                         ),
                         // sliderplugin::float_slider(0., -2., 2.)
-                        sliderplugin::discrete_slider(1., -2., 2., 1.)
+                        sliderplugin::discrete_slider(1., -2., 2., 1.),
+                        (
+                            WorkloadButton(slider_index),
+                            Button,
+                            Node {
+                                width: Val::Px(160.),
+                                margin: UiRect::all(Val::Px(10.)),
+
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            BorderRadius::all(Val::Px(8.)),
+                            BackgroundColor(TOOL_BUTTON_IDLE_COLOR),
+                            children![(
+                                WorkloadLabel(slider_index),
+                                Text::new(WorkloadKind::default().name()),
+                                TextFont {
+                                    font: asset_server.load(FONT_PATH),
+                                    font_size: 18.0,
+                                    ..default()
+                                },
+                                TextColor(Color::srgb(0.9, 0.9, 0.9)),
+                            )]
+                        ),
+                        (
+                            RateLimitButton(slider_index),
+                            Button,
+                            Node {
+                                width: Val::Px(140.),
+                                margin: UiRect::all(Val::Px(10.)),
+
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            BorderRadius::all(Val::Px(8.)),
+                            BackgroundColor(TOOL_BUTTON_IDLE_COLOR),
+                            children![(
+                                RateLimitLabel(slider_index),
+                                Text::new(rate_limit_text(settings.rate_limit)),
+                                TextFont {
+                                    font: asset_server.load(FONT_PATH),
+                                    font_size: 18.0,
+                                    ..default()
+                                },
+                                TextColor(Color::srgb(0.9, 0.9, 0.9)),
+                            )]
+                        )
                     ]
                 ));
             }
@@ -1,15 +1,56 @@
+use bevy::ecs::system::SystemParam;
 use bevy::window::PresentMode;
 use bevy::{asset::RenderAssetUsages, prelude::*, render::render_resource::*};
-use bevy::asset::Assets;
 use std::cmp::Eq;
 
+mod aging;
+mod canvas;
+mod config;
 mod sliderplugin;
+mod stats;
+mod race;
+mod tournament;
+mod sweep;
+mod scheduler;
+mod pi_lock;
+mod share_model;
+mod share_control;
+mod tools;
 mod main_controller;
 mod interface;
+mod workload;
 
 use main_controller::MainImageData;
+use crate::config::Config;
 use crate::main_controller::MainController;
 use crate::sliderplugin::SliderWrapper;
+use crate::workload::WorkloadKind;
+
+const GROUP_AMOUNT: u32 = 3;
+
+/// Workload and slider priority of every group, set together by a preset button
+struct Preset {
+    name: &'static str,
+    groups: [(WorkloadKind, i32); GROUP_AMOUNT as usize],
+    /// Whether every group's workers share a single CPU
+    pinned: bool,
+}
+
+const PRESETS: [Preset; 2] = [
+    // A low priority lock holder, a medium priority CPU hog and a high priority group that needs the same lock.
+    // With a core for every worker the hog would never get in the holder's way, so all of them share one CPU
+    Preset {
+        name: "Inversion preset (1 CPU)",
+        groups: [(WorkloadKind::LockHolder, -2), (WorkloadKind::MonteCarloPi, 0), (WorkloadKind::LockWaiter, 2)],
+        pinned: true,
+    },
+    // A low priority producer feeding a high priority consumer through a medium priority stage
+    Preset {
+        name: "Pipeline preset",
+        groups: [(WorkloadKind::Pipeline, -2), (WorkloadKind::Pipeline, 0), (WorkloadKind::Pipeline, 2)],
+        pinned: false,
+    },
+];
 
 #[derive(States, Debug, Clone, Eq, PartialEq, Hash, Default)]
 enum ProgramState {
@@ -25,7 +66,14 @@ struct PrioritiesContainer {
 }
 
 fn main() {
+    let config = Config::load();
+    if config.sweep.enabled {
+        sweep::run(&config);
+        return;
+    }
+
     App::new()
+        .insert_resource(config)
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
                 present_mode: PresentMode::Immediate,
@@ -35,85 +83,102 @@ fn main() {
         }))
         .init_state::<ProgramState>()
         .add_plugins(sliderplugin::SliderPlugin)
+        .add_plugins(canvas::CanvasPlugin)
+        .add_plugins(stats::StatsPlugin)
+        .add_plugins(tools::ToolsPlugin)
+        .add_plugins(race::RacePlugin)
+        .add_plugins(tournament::TournamentPlugin)
+        .add_plugins(share_control::ShareControlPlugin)
+        .add_plugins(aging::AgingPlugin)
         .add_systems(Startup, (setup, interface::setup_ui.after(setup)))
         .add_systems(Update, finish_loading.run_if(in_state(ProgramState::Loading)))
         .add_systems(OnEnter(ProgramState::Running), (interface::setup_sliders, main_controller_init))
-        .add_systems(Update, (update, update_priorities, start_button_controller, stop_button_controller)
+        .add_systems(Update, (update_priorities, start_button_controller, stop_button_controller, resize_button_controller,
+            workload_button_controller, preset_button_controller, lock_kind_button_controller,
+            rate_limit_button_controller, boundary_button_controller, reset_button_controller, reset_colors_button_controller)
             .run_if(in_state(ProgramState::Running)))
         .run();
 }
 
+/// Assets that have to be loaded before the program can run
+#[derive(Resource)]
+struct LoadingAssets(Vec<UntypedHandle>);
+
 fn finish_loading(
-    main_image_data: ResMut<MainImageData>,
-    images: ResMut<Assets<Image>>,
-    mut asset_events: EventReader<AssetEvent<Image>>,
+    server: Res<AssetServer>,
+    loading_assets: Res<LoadingAssets>,
     mut program_state: ResMut<NextState<ProgramState>>,
 ) {
-    for event in asset_events.read() {
-        if let AssetEvent::LoadedWithDependencies { id: _ } = event {
-            update_raw_ptr(images, main_image_data);
-            program_state.set(ProgramState::Running);
-            return;
-        }
+    // A missing obstacle mask shouldn't keep the program from starting
+    if loading_assets.0.iter().all(|handle| server.is_loaded_with_dependencies(handle) || server.load_state(handle).is_failed()) {
+        program_state.set(ProgramState::Running);
     }
 }
 
-/// NOTE: Only works for the first loaded asset. Careful
-fn update_raw_ptr(mut images: ResMut<Assets<Image>>, mut main_image_data: ResMut<MainImageData>) {
-    let image: &mut Image = match images.get_mut(&main_image_data.handle()) {
-        Some(value) => value,
-        _ => return
-    };
-    let img_raw_ptr = image.data.as_mut().expect("Image could not be initialized").as_mut_ptr();
-    main_image_data._set_data_ptr(img_raw_ptr as usize);
-}
-
 fn setup(
     mut commands: Commands,
     server: Res<AssetServer>,
+    config: Res<Config>,
 ) {
     commands.spawn(Camera2d);
-    let width = 1000;
-    let height = 1000;
+    let width = config.canvas_width;
+    let height = config.canvas_height;
 
-    let image = Image::new_fill(
+    let handle = server.add(canvas_image(width, height));
+    let font: Handle<Font> = server.load(interface::FONT_PATH);
+    let mut loading_assets = vec![handle.clone().untyped(), font.untyped()];
+    if let Some(path) = &config.obstacle_mask {
+        let mask: Handle<Image> = server.load(path);
+        loading_assets.push(mask.clone().untyped());
+        commands.insert_resource(tools::ObstacleMask(mask));
+    }
+    commands.insert_resource(LoadingAssets(loading_assets));
+    let main_image_data = MainImageData::new(handle, width as i32, height as i32);
+    main_image_data.canvas().set_boundary(config.boundary);
+    commands.insert_resource(main_image_data);
+    commands.insert_resource(PrioritiesContainer {priorities: Vec::new(), prev_priorities: Vec::new()});
+}
+
+fn canvas_image(width: u32, height: u32) -> Image {
+    Image::new_fill(
         Extent3d {
             width,
             height,
             depth_or_array_layers: 1,
         },
         TextureDimension::D2,
-        &[0, 0, 0, 255],
+        &main_controller::BACKGROUND_COLOR,
         TextureFormat::Rgba8UnormSrgb,
         RenderAssetUsages::RENDER_WORLD | RenderAssetUsages::MAIN_WORLD
-    );
-
-    let handle = server.add(image);
-    commands.insert_resource(MainImageData::new(handle, width as i32, height as i32, 0));
-    commands.insert_resource(PrioritiesContainer {priorities: Vec::new(), prev_priorities: Vec::new()});
+    )
 }
 
 fn update_priorities(
     mut priorities_container: ResMut<PrioritiesContainer>,
     sliders: Query<&SliderWrapper, With<SliderWrapper>>,
     controller: Res<MainController>,
+    control_mode: Res<share_control::ControlMode>,
 ) {
     let new_priorities = get_groups_priorities(sliders);
-    if new_priorities == priorities_container.priorities {return}
+    if new_priorities == priorities_container.priorities {
+        // Lock holders and waiters change all the time
+        if let Err(error) = controller.lend_priorities() {
+            error!("Couldn't lend priorities: {error}");
+        }
+        return;
+    }
 
     let prev_priorities = std::mem::replace(&mut priorities_container.priorities, new_priorities.clone());
     priorities_container.prev_priorities = prev_priorities;
 
-    controller.update_priorities(new_priorities).expect("Couldn't update priorities");
-}
-
-fn update(
-    main_image_data: Res<MainImageData>,
-    mut images: ResMut<Assets<Image>>,
-) {
-    // Get an image for bevy to update it on gpu
-    images.get_mut(&main_image_data.handle()).expect("Image not found");
-
+    if *control_mode == share_control::ControlMode::TargetShares {
+        controller.set_share_targets(Some(share_control::target_shares(&new_priorities)));
+        return;
+    }
+    // Linux only lets privileged processes lower a thread's nice value again
+    if let Err(error) = controller.update_priorities(new_priorities) {
+        error!("Couldn't update priorities: {error}");
+    }
 }
 
 fn get_groups_priorities(
@@ -127,8 +192,9 @@ fn get_groups_priorities(
 fn main_controller_init(
     mut commands: Commands,
     image_data: Res<MainImageData>,
+    config: Res<Config>,
 ) {
-    let mut main_controller = MainController::new(&image_data, 3);
+    let mut main_controller = MainController::new(&image_data, config.group_settings(GROUP_AMOUNT));
     main_controller.init();
 
     commands.insert_resource(main_controller);
@@ -173,3 +239,251 @@ fn stop_button_controller(
         };
     }
 }
+
+/// The canvas image and the node showing it
+#[derive(SystemParam)]
+struct Canvas<'w, 's> {
+    main_image_data: ResMut<'w, MainImageData>,
+    images: ResMut<'w, Assets<Image>>,
+    canvas_nodes: Query<'w, 's, &'static mut Node, With<interface::CanvasNode>>,
+}
+
+impl Canvas<'_, '_> {
+    /// Replaces the canvas with an empty one of the new size, keeping its boundary mode
+    fn resize(&mut self, width: u32, height: u32) {
+        let handle = self.main_image_data.handle();
+        let boundary = self.main_image_data.canvas().boundary();
+        self.images.insert(&handle, canvas_image(width, height));
+        *self.main_image_data = MainImageData::new(handle, width as i32, height as i32);
+        self.main_image_data.canvas().set_boundary(boundary);
+        self.canvas_nodes.iter_mut().for_each(|mut node| node.aspect_ratio = Some(width as f32 / height as f32));
+    }
+}
+
+fn resize_button_controller(
+    mut main_controller: ResMut<MainController>,
+    mut canvas: Canvas,
+    mut priorities_container: ResMut<PrioritiesContainer>,
+    mut config: ResMut<Config>,
+    resize_button: Query<(&mut BackgroundColor, &Interaction), interface::ButtonInteraction<interface::ResizeButton>>,
+    mut resolution_labels: Query<&mut Text, With<interface::ResolutionLabel>>,
+) {
+    for (mut bg_color, interaction) in resize_button {
+        match *interaction {
+            Interaction::Pressed => {
+                *bg_color = interface::TOOL_BUTTON_PRESSED_COLOR.into();
+
+                (config.canvas_width, config.canvas_height) = config.next_resolution();
+                let (width, height) = (config.canvas_width, config.canvas_height);
+
+                main_controller.terminate();
+                canvas.resize(width, height);
+                main_controller.resize(&canvas.main_image_data, config.group_settings(GROUP_AMOUNT));
+
+                // Forget applied priorities so the sliders get pushed to the new workers
+                priorities_container.priorities.clear();
+
+                resolution_labels.iter_mut().for_each(|mut label| label.0 = interface::resolution_text(width, height));
+            }
+            Interaction::Hovered => {
+                *bg_color = interface::TOOL_BUTTON_HOVERED.into();
+            }
+            Interaction::None => {
+                *bg_color = interface::TOOL_BUTTON_IDLE_COLOR.into();
+            }
+        };
+    }
+}
+
+fn workload_button_controller(
+    mut main_controller: ResMut<MainController>,
+    mut priorities_container: ResMut<PrioritiesContainer>,
+    workload_buttons: Query<(&mut BackgroundColor, &Interaction, &interface::WorkloadButton), Changed<Interaction>>,
+    mut workload_labels: Query<(&mut Text, &interface::WorkloadLabel)>,
+) {
+    for (mut bg_color, interaction, workload_button) in workload_buttons {
+        match *interaction {
+            Interaction::Pressed => {
+                *bg_color = interface::TOOL_BUTTON_PRESSED_COLOR.into();
+
+                let group_index = workload_button.0;
+                let workload = main_controller.workload(group_index).next();
+                main_controller.set_workload(group_index, workload);
+                priorities_container.priorities.clear();
+
+                workload_labels.iter_mut()
+                    .filter(|(_, label)| label.0 == group_index)
+                    .for_each(|(mut text, _)| text.0 = workload.name().to_string());
+            }
+            Interaction::Hovered => {
+                *bg_color = interface::TOOL_BUTTON_HOVERED.into();
+            }
+            Interaction::None => {
+                *bg_color = interface::TOOL_BUTTON_IDLE_COLOR.into();
+            }
+        };
+    }
+}
+
+fn preset_button_controller(
+    mut main_controller: ResMut<MainController>,
+    mut priorities_container: ResMut<PrioritiesContainer>,
+    preset_buttons: Query<(&mut BackgroundColor, &Interaction, &interface::PresetButton), Changed<Interaction>>,
+    mut sliders: Query<&mut SliderWrapper>,
+    mut workload_labels: Query<(&mut Text, &interface::WorkloadLabel)>,
+) {
+    for (mut bg_color, interaction, preset_button) in preset_buttons {
+        match *interaction {
+            Interaction::Pressed => {
+                *bg_color = interface::TOOL_BUTTON_PRESSED_COLOR.into();
+
+                let preset = &PRESETS[preset_button.0];
+                main_controller.set_pinned(preset.pinned);
+                for (group_index, (workload, _)) in preset.groups.iter().enumerate() {
+                    main_controller.set_workload(group_index, *workload);
+                }
+                // Sliders are listed in group order, as when reading the priorities
+                for (mut slider, (_, priority)) in sliders.iter_mut().zip(preset.groups) {
+                    slider.base_mut().value = priority as f32;
+                }
+                priorities_container.priorities.clear();
+
+                for (mut text, label) in &mut workload_labels {
+                    text.0 = preset.groups[label.0].0.name().to_string();
+                }
+            }
+            Interaction::Hovered => {
+                *bg_color = interface::TOOL_BUTTON_HOVERED.into();
+            }
+            Interaction::None => {
+                *bg_color = interface::TOOL_BUTTON_IDLE_COLOR.into();
+            }
+        };
+    }
+}
+
+fn lock_kind_button_controller(
+    lock_kind_button: Query<(&mut BackgroundColor, &Interaction), interface::ButtonInteraction<interface::LockKindButton>>,
+    mut lock_kind_labels: Query<&mut Text, With<interface::LockKindLabel>>,
+) {
+    for (mut bg_color, interaction) in lock_kind_button {
+        match *interaction {
+            Interaction::Pressed => {
+                *bg_color = interface::TOOL_BUTTON_PRESSED_COLOR.into();
+
+                let lock_kind = workload::lock_kind().next();
+                workload::set_lock_kind(lock_kind);
+                lock_kind_labels.iter_mut().for_each(|mut label| label.0 = lock_kind.name().to_string());
+            }
+            Interaction::Hovered => {
+                *bg_color = interface::TOOL_BUTTON_HOVERED.into();
+            }
+            Interaction::None => {
+                *bg_color = interface::TOOL_BUTTON_IDLE_COLOR.into();
+            }
+        };
+    }
+}
+
+fn rate_limit_button_controller(
+    main_controller: Res<MainController>,
+    rate_limit_buttons: Query<(&mut BackgroundColor, &Interaction, &interface::RateLimitButton), Changed<Interaction>>,
+    mut rate_limit_labels: Query<(&mut Text, &interface::RateLimitLabel)>,
+) {
+    for (mut bg_color, interaction, rate_limit_button) in rate_limit_buttons {
+        match *interaction {
+            Interaction::Pressed => {
+                *bg_color = interface::TOOL_BUTTON_PRESSED_COLOR.into();
+
+                let group_index = rate_limit_button.0;
+                let limit = main_controller::RateLimit::next(main_controller.rate_limit(group_index));
+                main_controller.set_rate_limit(group_index, limit);
+
+                rate_limit_labels.iter_mut()
+                    .filter(|(_, label)| label.0 == group_index)
+                    .for_each(|(mut text, _)| text.0 = interface::rate_limit_text(limit));
+            }
+            Interaction::Hovered => {
+                *bg_color = interface::TOOL_BUTTON_HOVERED.into();
+            }
+            Interaction::None => {
+                *bg_color = interface::TOOL_BUTTON_IDLE_COLOR.into();
+            }
+        };
+    }
+}
+
+fn boundary_button_controller(
+    main_image_data: Res<MainImageData>,
+    mut config: ResMut<Config>,
+    boundary_button: Query<(&mut BackgroundColor, &Interaction), interface::ButtonInteraction<interface::BoundaryButton>>,
+    mut boundary_labels: Query<&mut Text, With<interface::BoundaryLabel>>,
+) {
+    for (mut bg_color, interaction) in boundary_button {
+        match *interaction {
+            Interaction::Pressed => {
+                *bg_color = interface::TOOL_BUTTON_PRESSED_COLOR.into();
+
+                let boundary = main_image_data.canvas().boundary().next();
+                main_image_data.canvas().set_boundary(boundary);
+                config.boundary = boundary;
+
+                boundary_labels.iter_mut().for_each(|mut label| label.0 = boundary.name().to_string());
+            }
+            Interaction::Hovered => {
+                *bg_color = interface::TOOL_BUTTON_HOVERED.into();
+            }
+            Interaction::None => {
+                *bg_color = interface::TOOL_BUTTON_IDLE_COLOR.into();
+            }
+        };
+    }
+}
+
+fn reset_button_controller(
+    mut main_controller: ResMut<MainController>,
+    mut priorities_container: ResMut<PrioritiesContainer>,
+    config: Res<Config>,
+    reset_button: Query<(&mut BackgroundColor, &Interaction), interface::ButtonInteraction<interface::ResetButton>>,
+) {
+    for (mut bg_color, interaction) in reset_button {
+        match *interaction {
+            Interaction::Pressed => {
+                *bg_color = interface::TOOL_BUTTON_PRESSED_COLOR.into();
+
+                main_controller.reset(config.keep_colors);
+                // Forget applied priorities so the sliders get pushed to the new workers
+                priorities_container.priorities.clear();
+            }
+            Interaction::Hovered => {
+                *bg_color = interface::TOOL_BUTTON_HOVERED.into();
+            }
+            Interaction::None => {
+                *bg_color = interface::TOOL_BUTTON_IDLE_COLOR.into();
+            }
+        };
+    }
+}
+
+fn reset_colors_button_controller(
+    mut config: ResMut<Config>,
+    reset_colors_button: Query<(&mut BackgroundColor, &Interaction), interface::ButtonInteraction<interface::ResetColorsButton>>,
+    mut reset_colors_labels: Query<&mut Text, With<interface::ResetColorsLabel>>,
+) {
+    for (mut bg_color, interaction) in reset_colors_button {
+        match *interaction {
+            Interaction::Pressed => {
+                *bg_color = interface::TOOL_BUTTON_PRESSED_COLOR.into();
+
+                config.keep_colors = !config.keep_colors;
+                reset_colors_labels.iter_mut().for_each(|mut label| label.0 = interface::reset_colors_text(config.keep_colors).to_string());
+            }
+            Interaction::Hovered => {
+                *bg_color = interface::TOOL_BUTTON_HOVERED.into();
+            }
+            Interaction::None => {
+                *bg_color = interface::TOOL_BUTTON_IDLE_COLOR.into();
+            }
+        };
+    }
+}
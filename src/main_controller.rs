@@ -1,52 +1,79 @@
 use bevy::log;
 use bevy::prelude::*;
 
-use std::sync::RwLock;
+use std::sync::{Mutex, MutexGuard, RwLock};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use std::sync::OnceLock;
 use std::sync::mpsc;
 
 use rand::Rng;
 
-use windows::Win32::System::Threading::{
-    GetCurrentThreadId, OpenThread, SetThreadPriority,
-    THREAD_SET_INFORMATION, THREAD_PRIORITY
-};
-use windows::core;
+use crate::canvas::CanvasBuffer;
+use crate::pi_lock::{self, LockCounters};
+use crate::scheduler;
+use crate::share_control::{self, ShareControl};
+use crate::workload::{self, seed, Painter, StageQueue, TileQueue, Workload, WorkloadKind, WorkloadSettings};
 
 #[derive(Resource, Clone)]
 pub struct MainImageData {
     handle: Handle<Image>,
-    width: i32,
-    height: i32,
-    data_ptr: usize,
+    canvas: Arc<CanvasBuffer>,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
-pub struct Color(u8, u8, u8, u8);
+pub struct Color(pub u8, pub u8, pub u8, pub u8);
+
+pub const BACKGROUND_COLOR: [u8; 4] = [0, 0, 0, 255];
+
+impl From<Color> for bevy::color::Color {
+    fn from(color: Color) -> bevy::color::Color {
+        bevy::color::Color::srgba_u8(color.0, color.1, color.2, color.3)
+    }
+}
 
 impl MainImageData {
-    pub fn new(handle: Handle<Image>, width: i32, height: i32, data_ptr: usize) -> MainImageData {
-        MainImageData { handle, width, height, data_ptr }
+    pub fn new(handle: Handle<Image>, width: i32, height: i32) -> MainImageData {
+        let canvas = Arc::new(CanvasBuffer::new(width as u32, height as u32, BACKGROUND_COLOR));
+        MainImageData { handle, canvas }
     }
     pub fn handle(&self) -> Handle<Image> {self.handle.clone()}
-    pub fn width(&self) -> i32 {self.width}
-    pub fn height(&self) -> i32 {self.height}
-    pub fn data_ptr(&self) -> usize {self.data_ptr}
-    pub fn _set_data_ptr(&mut self, value: usize) {self.data_ptr = value}
+    pub fn width(&self) -> i32 {self.canvas.width() as i32}
+    pub fn height(&self) -> i32 {self.canvas.height() as i32}
+    pub fn canvas(&self) -> &CanvasBuffer {&self.canvas}
 }
 
 #[derive(Resource)]
 pub struct MainController {
     groups: Vec<WorkerGroup>,
+    share_control: Mutex<ShareControl>,
+    /// Priorities last asked for, before any are lent to lock holders
+    requested_priorities: Mutex<Vec<i32>>,
     deletion_handler: Box<DeletionHandler>,
 }
 impl MainController {
-    pub fn new(image_data: &MainImageData, group_amount: u32) -> MainController {
+    /// Creates one group per entry of `group_settings`, each connected to the next one by a pipeline queue
+    /// and all of them sharing one tile queue
+    pub fn new(image_data: &MainImageData, mut group_settings: Vec<WorkloadSettings>) -> MainController {
         let image_data = Arc::new(image_data.clone());
+        let tiles = Arc::new(TileQueue::new(image_data.width(), image_data.height()));
+        let queues: Vec<Arc<StageQueue>> = group_settings[..group_settings.len().saturating_sub(1)].iter()
+            .map(|producer| Arc::new(StageQueue::new(producer.pipeline.capacity)))
+            .collect();
+        for (group_index, settings) in group_settings.iter_mut().enumerate() {
+            settings.pipeline.input = group_index.checked_sub(1).map(|queue_index| queues[queue_index].clone());
+            settings.pipeline.output = queues.get(group_index).cloned();
+            settings.tiles.queue = Some(tiles.clone());
+        }
+
         MainController {
-            groups: (0..group_amount).map(|_| WorkerGroup::new(image_data.clone())).collect(),
+            groups: group_settings.into_iter()
+                .map(|settings| WorkerGroup::new(image_data.clone(), WorkloadKind::default(), settings, random_color()))
+                .collect(),
+            share_control: Mutex::new(ShareControl::default()),
+            requested_priorities: Mutex::new(Vec::new()),
             deletion_handler: Box::new(DeletionHandler::new()),
         }
     }
@@ -54,6 +81,7 @@ impl MainController {
         (&mut self.groups).into_iter().for_each(|group| group.init());
     }
     pub fn start_all(&self) -> Result<(), std::sync::PoisonError<std::sync::RwLockWriteGuard<'_, WorkerStatus>>> {
+        workload::resume_phases();
         for group in &self.groups {
             group.start()?;
         }
@@ -63,20 +91,238 @@ impl MainController {
         for group in &self.groups {
             group.stop()?;
         }
+        // Stopped groups would otherwise count as a slow phase
+        workload::pause_phases();
         Ok(())
     }
-    pub fn update_priorities(&self, priorities: Vec<i32>) -> core::Result<()> {
-        (&self.groups).iter().zip(priorities).try_for_each(|(group, priority)| group.set_priority(priority))?;
-        Ok(())
+    /// Applies `priorities`, with the ones lent to lock holders on top, see [`MainController::lend_priorities`]
+    pub fn update_priorities(&self, priorities: Vec<i32>) -> Result<(), PriorityErrors> {
+        *self.requested_priorities.lock().unwrap() = priorities;
+        self.lend_priorities()
     }
-    
-}
+    /// Priority inheritance for the groups, as lock holders and waiters come and go:
+    /// a group holding a PI lock runs with the highest priority of the groups waiting for one.
+    /// Raising a priority takes CAP_SYS_NICE on Linux. Where the holder couldn't be raised, the other groups
+    /// below the waiters' priority are held back instead, as if the holder ran above them
+    pub fn lend_priorities(&self) -> Result<(), PriorityErrors> {
+        let requested = self.requested_priorities.lock().unwrap().clone();
+        let waiting_priority = self.groups.iter().zip(&requested)
+            .filter(|(group, _)| group.control.locks.waiting() > 0)
+            .map(|(_, priority)| *priority)
+            .max();
+        let lent_priorities: Vec<i32> = self.groups.iter().zip(&requested)
+            .map(|(group, priority)| match waiting_priority {
+                Some(waiting_priority) if group.control.locks.holding() > 0 => waiting_priority.max(*priority),
+                _ => *priority,
+            })
+            .collect();
+        let result = self.apply_priorities(lent_priorities.clone());
+
+        // The share controller throttles the groups itself
+        if self.share_control.lock().unwrap().targets().is_none() {
+            let lending_failed = self.groups.iter().zip(&lent_priorities).zip(&requested)
+                .any(|((group, lent), priority)| lent > priority && group.applied_priority() != Some(*lent));
+            for (group, priority) in self.groups.iter().zip(&requested) {
+                let locks = &group.control.locks;
+                let held_back = lending_failed && locks.holding() == 0 && locks.waiting() == 0
+                    && waiting_priority.is_some_and(|waiting_priority| *priority < waiting_priority);
+                group.control.set_throttle(if held_back { 1. } else { 0. });
+            }
+        }
+        result
+    }
+    /// Applies the priorities that changed. A group that fails doesn't keep the others from getting theirs,
+    /// and its failure is only reported until it takes a priority again
+    fn apply_priorities(&self, priorities: Vec<i32>) -> Result<(), PriorityErrors> {
+        let mut errors = Vec::new();
+        for (group_index, (group, priority)) in self.groups.iter().zip(priorities).enumerate() {
+            if let Err(error) = group.set_priority(priority) && !group.failure_reported.swap(true, Ordering::Relaxed) {
+                errors.push((group_index, error));
+            }
+        }
+        if errors.is_empty() { Ok(()) } else { Err(PriorityErrors(errors)) }
+    }
+    /// Makes the groups track target shares of throughput, `None` hands control back to plain priorities
+    pub fn set_share_targets(&self, targets: Option<Vec<f64>>) {
+        if targets.is_none() {
+            self.groups.iter().for_each(|group| group.control.set_throttle(0.));
+        }
+        self.share_control.lock().unwrap().set_targets(targets);
+    }
+    /// One step of the share feedback loop, adjusting priorities and throttles. Does nothing without targets
+    pub fn step_share_control(&self, elapsed: Duration) -> Result<(), PriorityErrors> {
+        let progress: Vec<u64> = self.groups.iter().map(|group| group.stats.progress()).collect();
+        let Some(mut efforts) = self.share_control.lock().unwrap().step(&progress, elapsed) else { return Ok(()) };
+
+        // Userspace priority inheritance: a group holding a PI lock runs with the highest effort of the groups waiting for one
+        let waiting_effort = self.groups.iter().zip(&efforts)
+            .filter(|(group, _)| group.control.locks.waiting() > 0)
+            .map(|(_, effort)| *effort)
+            .fold(f64::NEG_INFINITY, f64::max);
+        for (group, effort) in self.groups.iter().zip(efforts.iter_mut()) {
+            if group.control.locks.holding() > 0 {
+                *effort = effort.max(waiting_effort);
+            }
+        }
+        let wanted_priorities: Vec<i32> = efforts.iter().copied().map(share_control::priority_for_effort).collect();
+        let mut result = self.update_priorities(wanted_priorities.clone());
+
+        // Raising a priority takes CAP_SYS_NICE on Linux. Where a group couldn't be raised, every effort moves down
+        // by the levels it's missing, so the others get lowered or throttled instead
+        let shortfall = self.groups.iter().zip(&wanted_priorities)
+            .filter_map(|(group, wanted)| group.applied_priority().map(|applied| wanted - applied))
+            .fold(0, i32::max);
+        if shortfall > 0 {
+            efforts.iter_mut().for_each(|effort| *effort -= shortfall as f64);
+            result = result.and(self.update_priorities(efforts.iter().copied().map(share_control::priority_for_effort).collect()));
+        }
+        for (group, effort) in self.groups.iter().zip(&efforts) {
+            group.control.set_throttle(share_control::throttle_for_effort(*effort));
+        }
+        result
+    }
+    pub fn share_control(&self) -> MutexGuard<'_, ShareControl> {
+        self.share_control.lock().unwrap()
+    }
+    pub fn rate_limit(&self, group_index: usize) -> Option<RateLimit> {
+        self.groups[group_index].control.rate_limit()
+    }
+    /// Takes effect at the workers' next batch checkpoint
+    pub fn set_rate_limit(&self, group_index: usize, limit: Option<RateLimit>) {
+        self.groups[group_index].control.set_rate_limit(limit);
+    }
+    /// Whether any group is currently allowed to run
+    pub fn is_running(&self) -> bool {
+        self.groups.iter().any(|group| group.is_running())
+    }
+    /// Whether the group is running but hasn't made progress for longer than `threshold`
+    pub fn is_starving(&self, group_index: usize, threshold: Duration) -> bool {
+        let group = &self.groups[group_index];
+        group.is_running() && group.stats.since_progress() > threshold
+    }
+    pub fn group_amount(&self) -> usize {
+        self.groups.len()
+    }
+    pub fn color(&self, group_index: usize) -> Color {
+        self.groups[group_index].color
+    }
+    pub fn stats(&self, group_index: usize) -> &GroupStats {
+        &self.groups[group_index].stats
+    }
+    pub fn workload(&self, group_index: usize) -> WorkloadKind {
+        self.groups[group_index].workload
+    }
+    /// Pixels currently painted in each group's color
+    pub fn territory(&self) -> Vec<u64> {
+        let Some(group) = self.groups.first() else { return Vec::new() };
+        let colors: Vec<[u8; 4]> = self.groups.iter().map(|group| [group.color.0, group.color.1, group.color.2, group.color.3]).collect();
+        group.image_data.canvas().count_colors(&colors)
+    }
+    /// Bounding box of the group's cluster, `None` while nothing got attached
+    pub fn cluster_bounds(&self, group_index: usize) -> Option<([i32; 2], [i32; 2])> {
+        self.groups[group_index].painter.bounds().get()
+    }
+    /// Queue the group takes pipeline items from, `None` for the first group
+    pub fn input_queue(&self, group_index: usize) -> Option<&StageQueue> {
+        self.groups[group_index].settings.pipeline.input.as_deref()
+    }
+    /// Queue the group hands pipeline items to, `None` for the last group
+    pub fn output_queue(&self, group_index: usize) -> Option<&StageQueue> {
+        self.groups[group_index].settings.pipeline.output.as_deref()
+    }
+    /// Tile queue every group renders from
+    pub fn tiles(&self) -> Option<&TileQueue> {
+        self.groups.first().and_then(|group| group.settings.tiles.queue.as_deref())
+    }
+    /// Priority the group's workers actually run with, `None` until one was applied
+    pub fn applied_priority(&self, group_index: usize) -> Option<i32> {
+        self.groups[group_index].applied_priority()
+    }
+    pub fn worker_count(&self, group_index: usize) -> usize {
+        self.groups[group_index].workers.len()
+    }
+    /// Runs every group's workers on a single CPU, or lets them spread over all CPUs again.
+    /// Respawned workers keep the setting
+    pub fn set_pinned(&mut self, pinned: bool) {
+        for group in &mut self.groups {
+            if group.settings.pinned == pinned { continue }
+            group.settings.pinned = pinned;
+            group.pin(pinned);
+        }
+    }
+    /// Processor time the group's workers have used so far, in user and kernel mode
+    pub fn cpu_time(&self, group_index: usize) -> Duration {
+        self.groups[group_index].workers.iter().map(|worker| worker.cpu_time().unwrap_or_default()).sum()
+    }
+    /// Respawns the group's workers with a different workload. The new workers start idle
+    pub fn set_workload(&mut self, group_index: usize, workload: WorkloadKind) {
+        // The first group to start rendering starts over with every tile
+        let rendering = self.groups.iter().any(|group| group.workload == WorkloadKind::TileRender);
+        if let Some(tiles) = self.tiles().filter(|_| workload == WorkloadKind::TileRender && !rendering) {
+            tiles.reset();
+        }
+        let group = &mut self.groups[group_index];
+        group.terminate();
+        // Items of a stage that leaves the pipeline would be stale, and a stage that joins starts out empty
+        if group.workload == WorkloadKind::Pipeline || workload == WorkloadKind::Pipeline {
+            let pipeline = &group.settings.pipeline;
+            pipeline.input.iter().chain(&pipeline.output).for_each(|queue| queue.clear());
+        }
 
-fn set_thread_priority(priority: i32, pid: u32) -> core::Result<()> {
-    log::info!("Set {} to priority {}", pid, priority);
-    let thread_handle = unsafe { OpenThread(THREAD_SET_INFORMATION, false, pid)? };
-    unsafe { SetThreadPriority(thread_handle, THREAD_PRIORITY(priority))? };
-    Ok(())
+        let mut new_group = WorkerGroup::new(group.image_data.clone(), workload, group.settings.clone(), group.color);
+        // Limits set at runtime outlive the workers
+        new_group.control.set_rate_limit(group.control.rate_limit());
+        new_group.init();
+        *group = new_group;
+    }
+    /// Respawns every group on a resized canvas. Workloads are switched before the canvas gets seeded,
+    /// and what was set at runtime carries over: rate limits, throttles, share targets and pinning.
+    /// The new workers start idle
+    pub fn resize(&mut self, image_data: &MainImageData, group_settings: Vec<WorkloadSettings>) {
+        self.terminate();
+        let mut resized = MainController::new(image_data, group_settings);
+        for (new_group, group) in resized.groups.iter_mut().zip(&self.groups) {
+            new_group.workload = group.workload;
+            new_group.settings.pinned = group.settings.pinned;
+            new_group.control.set_rate_limit(group.control.rate_limit());
+            new_group.control.set_throttle(group.control.throttle());
+        }
+        resized.share_control = std::mem::take(&mut self.share_control);
+        resized.requested_priorities = std::mem::take(&mut self.requested_priorities);
+        resized.init();
+        *self = resized;
+    }
+    /// Plants the group's seed shape centered on the given pixel
+    pub fn plant_seed(&self, group_index: usize, pos: [i32; 2]) {
+        let group = &self.groups[group_index];
+        seed::plant(&group.painter, group.settings.seed.shape, pos);
+    }
+    /// Starts a fresh race on the same canvas: respawns every group with its workload and settings,
+    /// clears the canvas and replants the seeds. The new workers start idle
+    pub fn reset(&mut self, keep_colors: bool) {
+        self.terminate();
+        if let Some(group) = self.groups.first() {
+            group.image_data.canvas().clear();
+        }
+        self.groups.iter().filter_map(|group| group.settings.pipeline.output.as_ref()).for_each(|queue| queue.clear());
+        if let Some(tiles) = self.tiles() {
+            tiles.reset();
+        }
+
+        for group in &mut self.groups {
+            let color = if keep_colors { group.color } else { random_color() };
+            let mut new_group = WorkerGroup::new(group.image_data.clone(), group.workload, group.settings.clone(), color);
+            new_group.control.set_rate_limit(group.control.rate_limit());
+            new_group.init();
+            *group = new_group;
+        }
+    }
+    /// Stops every worker thread and waits for them to exit
+    pub fn terminate(&mut self) {
+        self.groups.iter_mut().for_each(|group| group.terminate());
+        // Resetting and resizing start counting phases over
+        workload::reset_phases();
+    }
 }
 
 #[derive(Clone, Default, PartialEq, Eq)]
@@ -84,31 +330,303 @@ pub enum WorkerStatus {
     #[default]
     Idle,
     Running,
+    Terminated,
+}
+
+/// Counters the workers of a group keep up to date while they run
+#[derive(Default)]
+pub struct GroupStats {
+    progress: AtomicU64,
+    aborted: AtomicU64,
+    /// One histogram per [`Timing`], in the order of [`Timing::ALL`]
+    timings: [LatencyHistogram; Timing::ALL.len()],
+    /// When the group last made progress, or was started, in milliseconds since [`clock_ms`] started
+    last_progress_ms: AtomicU64,
+}
+
+/// Durations workloads measure while they run, each kept in its own histogram
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timing {
+    /// How late a sleeping workload woke up
+    WakeupLatency,
+    /// Time spent waiting for the lock shared between groups
+    LockWait,
+    /// Time spent blocked on a full output or an empty input queue of the pipeline
+    QueueWait,
+    /// Time from production to retirement of the pipeline items the group retired
+    ItemLatency,
+    /// Time spent at the phase barrier waiting for the other groups
+    BarrierWait,
+}
+
+impl Timing {
+    pub const ALL: [Timing; 5] = [Timing::WakeupLatency, Timing::LockWait, Timing::QueueWait, Timing::ItemLatency, Timing::BarrierWait];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Timing::WakeupLatency => "late by",
+            Timing::LockWait => "lock wait",
+            Timing::QueueWait => "blocked",
+            Timing::ItemLatency => "end-to-end",
+            Timing::BarrierWait => "barrier wait",
+        }
+    }
+}
+
+/// Milliseconds on a clock shared by every group
+fn clock_ms() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_millis() as u64
+}
+
+impl GroupStats {
+    /// Total progress, counted in the group's workload metric
+    pub fn progress(&self) -> u64 {self.progress.load(Ordering::Relaxed)}
+    /// Units of work that were cut short
+    pub fn aborted(&self) -> u64 {self.aborted.load(Ordering::Relaxed)}
+    pub fn timing(&self, timing: Timing) -> &LatencyHistogram {&self.timings[timing as usize]}
+    /// Counts a unit of work that was cut short, e.g. a walker lost at an absorbing wall
+    pub fn count_aborted(&self) {
+        self.aborted.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn record_timing(&self, timing: Timing, duration: Duration) {
+        self.timings[timing as usize].record(duration);
+    }
+    /// Time since the group last made progress. Being idle counts too, the clock restarts when the group starts
+    pub fn since_progress(&self) -> Duration {
+        Duration::from_millis(clock_ms().saturating_sub(self.last_progress_ms.load(Ordering::Relaxed)))
+    }
+    fn record(&self, progress: u64) {
+        self.progress.fetch_add(progress, Ordering::Relaxed);
+        if progress > 0 {
+            self.last_progress_ms.store(clock_ms(), Ordering::Relaxed);
+        }
+    }
+}
+
+/// Longest a worker sleeps at once while held back, so terminating a group stays quick
+const MAX_SLEEP_SLICE: Duration = Duration::from_millis(100);
+/// Tokens a bucket can save up, in seconds of its rate
+const BUCKET_BURST_SECONDS: f64 = 0.1;
+
+/// What a rate limit counts
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RateUnit {
+    /// Calls to the workload's `work`, one walk for DLA
+    Walks,
+    /// Progress in the workload's metric, attachments for DLA
+    Progress,
+}
+
+impl RateUnit {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RateUnit::Walks => "walks",
+            RateUnit::Progress => "progress",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RateLimit {
+    pub per_second: f64,
+    pub unit: RateUnit,
+}
+
+/// Limits the rate limit button cycles through, per second
+const RATE_LIMIT_PRESETS: [f64; 4] = [100., 1000., 10000., 100000.];
+
+impl RateLimit {
+    /// Next preset after `limit`, keeping its unit, and no limit after the last one
+    pub fn next(limit: Option<RateLimit>) -> Option<RateLimit> {
+        let Some(limit) = limit else {
+            return Some(RateLimit { per_second: RATE_LIMIT_PRESETS[0], unit: RateUnit::Walks });
+        };
+        RATE_LIMIT_PRESETS.iter()
+            .find(|per_second| **per_second > limit.per_second)
+            .map(|per_second| RateLimit { per_second: *per_second, ..limit })
+    }
+}
+
+/// Tokens refill at the limit's rate, workers pay for their calls in chunks of at most a burst
+/// and wait until the bucket isn't in debt anymore
+struct TokenBucket {
+    limit: Option<RateLimit>,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Default for TokenBucket {
+    fn default() -> TokenBucket {
+        TokenBucket { limit: None, tokens: 0., refilled: Instant::now() }
+    }
+}
+
+/// Knobs the workers of a group read at every batch checkpoint
+#[derive(Default)]
+pub struct GroupControl {
+    /// Fraction of the time workers sleep instead of working, in thousandths
+    throttle_permille: AtomicU32,
+    bucket: Mutex<TokenBucket>,
+    /// Priority inheritance locks the workers hold or wait for
+    locks: Arc<LockCounters>,
+}
+
+impl GroupControl {
+    pub fn throttle(&self) -> f64 {self.throttle_permille.load(Ordering::Relaxed) as f64 / 1000.}
+    /// Clamped to 0..0.95, a group that never runs can't be measured anymore
+    pub fn set_throttle(&self, throttle: f64) {
+        self.throttle_permille.store((throttle.clamp(0., 0.95) * 1000.) as u32, Ordering::Relaxed);
+    }
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.bucket.lock().unwrap().limit
+    }
+    /// Any debt from an earlier limit is forgiven
+    pub fn set_rate_limit(&self, limit: Option<RateLimit>) {
+        *self.bucket.lock().unwrap() = TokenBucket { limit, ..default() };
+    }
+    /// Calls a worker makes before paying for them. Low limits get paid in chunks smaller than a batch,
+    /// so the workers don't run a whole batch each and then stall for seconds
+    fn charge_interval(&self, batch_size: u32) -> u32 {
+        match self.bucket.lock().unwrap().limit {
+            Some(limit) => ((limit.per_second * BUCKET_BURST_SECONDS) as u32).clamp(1, batch_size),
+            None => batch_size,
+        }
+    }
+    /// Takes what the calls since the last charge cost out of the bucket
+    fn charge(&self, walks: u64, progress: u64) {
+        let mut bucket = self.bucket.lock().unwrap();
+        let Some(limit) = bucket.limit else { return };
+        bucket.tokens -= match limit.unit {
+            RateUnit::Walks => walks,
+            RateUnit::Progress => progress,
+        } as f64;
+    }
+    /// How long until the bucket is out of debt, zero without a limit
+    fn token_wait(&self) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap();
+        let Some(limit) = bucket.limit else { return Duration::ZERO };
+
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.refilled).as_secs_f64() * limit.per_second;
+        bucket.tokens = (bucket.tokens + refill).min((limit.per_second * BUCKET_BURST_SECONDS).max(1.));
+        bucket.refilled = now;
+        if bucket.tokens >= 0. { return Duration::ZERO }
+        Duration::from_secs_f64(-bucket.tokens / limit.per_second)
+    }
+}
+
+/// Upper bounds of the latency histogram buckets in microseconds, the last bucket takes everything above
+pub const LATENCY_BUCKETS_US: [u64; 10] = [50, 100, 250, 500, 1_000, 2_000, 5_000, 10_000, 20_000, u64::MAX];
+
+#[derive(Default)]
+pub struct LatencyHistogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS_US.len()],
+    total_us: AtomicU64,
+    max_us: AtomicU64,
+}
+
+impl LatencyHistogram {
+    pub fn record(&self, latency: Duration) {
+        let latency_us = latency.as_micros() as u64;
+        let bucket = LATENCY_BUCKETS_US.iter().position(|bound| latency_us < *bound).unwrap_or(LATENCY_BUCKETS_US.len() - 1);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.total_us.fetch_add(latency_us, Ordering::Relaxed);
+        self.max_us.fetch_max(latency_us, Ordering::Relaxed);
+    }
+    pub fn buckets(&self) -> [u64; LATENCY_BUCKETS_US.len()] {
+        std::array::from_fn(|bucket| self.buckets[bucket].load(Ordering::Relaxed))
+    }
+    pub fn count(&self) -> u64 {self.buckets().iter().sum()}
+    pub fn mean(&self) -> Duration {
+        let count = self.count();
+        if count == 0 { return Duration::ZERO }
+        Duration::from_micros(self.total_us.load(Ordering::Relaxed) / count)
+    }
+    pub fn max(&self) -> Duration {Duration::from_micros(self.max_us.load(Ordering::Relaxed))}
+    pub fn total(&self) -> Duration {Duration::from_micros(self.total_us.load(Ordering::Relaxed))}
+    /// Upper bound of the bucket the given quantile falls into, in microseconds
+    pub fn quantile_bound_us(&self, quantile: f64) -> u64 {
+        let buckets = self.buckets();
+        let target = (quantile * buckets.iter().sum::<u64>() as f64).ceil() as u64;
+        let mut seen = 0;
+        for (bucket, count) in buckets.iter().enumerate() {
+            seen += count;
+            if seen >= target { return LATENCY_BUCKETS_US[bucket] }
+        }
+        u64::MAX
+    }
+}
+
+/// Groups whose priority couldn't be applied, with the error each of them got
+#[derive(Debug)]
+pub struct PriorityErrors(Vec<(usize, scheduler::Error)>);
+
+impl std::fmt::Display for PriorityErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (position, (group_index, error)) in self.0.iter().enumerate() {
+            if position > 0 { write!(f, ", ")? }
+            write!(f, "G{group_index}: {error}")?;
+        }
+        Ok(())
+    }
 }
 
 pub struct WorkerGroup {
     workers: Vec<Worker>,
+    /// Priority the workers run with, once one was applied to all of them
+    applied_priority: Mutex<Option<i32>>,
+    /// Whether the failure to apply the last priority was reported already
+    failure_reported: AtomicBool,
     status: Arc<RwLock<WorkerStatus>>,
+    stats: Arc<GroupStats>,
+    control: Arc<GroupControl>,
+    image_data: Arc<MainImageData>,
+    workload: WorkloadKind,
+    settings: WorkloadSettings,
+    color: Color,
+    painter: Painter,
 }
 
 impl WorkerGroup {
-    // TODO: disbanding groups and killing threads
-    pub fn new(image_data: Arc<MainImageData>) -> WorkerGroup {
+    pub fn new(image_data: Arc<MainImageData>, workload: WorkloadKind, settings: WorkloadSettings, color: Color) -> WorkerGroup {
         let mut group = WorkerGroup {
             workers: Vec::new(),
-            status: Arc::new(RwLock::new(WorkerStatus::default()))
+            applied_priority: Mutex::new(None),
+            failure_reported: AtomicBool::new(false),
+            status: Arc::new(RwLock::new(WorkerStatus::default())),
+            stats: Arc::new(GroupStats::default()),
+            control: Arc::new(GroupControl::default()),
+            painter: Painter::new(image_data.clone(), color),
+            image_data,
+            workload,
+            settings,
+            color,
         };
-        group.workers = (0..4).map(|_| Worker::new(image_data.clone(), group.status.clone())).collect();
+        group.control.set_rate_limit(group.settings.rate_limit);
+        group.workers = (0..4).map(|_| Worker::new(group.status.clone(), group.stats.clone(), group.control.clone())).collect();
         group
     }
     pub fn init(&mut self) {
-        let color = random_color();
-        log::info!("{:?}", color);
-        let pos = self.workers[0].get_random_pos();
-        unsafe { self.workers[0].set_color(pos[0], pos[1], color) };
-        (&mut self.workers).into_iter().for_each(|worker| {worker.spawn(color)});
+        log::info!("{:?} running {}", self.color, self.workload.name());
+        let mut workloads: Vec<Box<dyn Workload>> = self.workers.iter().map(|_| self.workload.create(&self.settings)).collect();
+        workloads[0].init(&self.painter);
+        let painter = &self.painter;
+        self.workers.iter_mut().zip(workloads).for_each(|(worker, workload)| {worker.spawn(workload, painter.clone())});
+        if self.settings.pinned {
+            self.pin(true);
+        }
+    }
+    /// A worker that can't be pinned keeps running wherever the scheduler puts it
+    fn pin(&self, pinned: bool) {
+        for worker in &self.workers {
+            if let Err(error) = scheduler::pin_thread(pinned, worker.pid) {
+                log::error!("Couldn't pin worker thread {}: {error}", worker.pid);
+            }
+        }
     }
     pub fn start(&self) -> Result<(), std::sync::PoisonError<std::sync::RwLockWriteGuard<'_, WorkerStatus>>> {
+        self.stats.last_progress_ms.store(clock_ms(), Ordering::Relaxed);
         *self.status.write()? = WorkerStatus::Running;
         Ok(())
     }
@@ -116,107 +634,140 @@ impl WorkerGroup {
         *self.status.write()? = WorkerStatus::Idle;
         Ok(())
     }
-    pub fn set_priority(&self, priority: i32) -> core::Result<()>{
+    pub fn is_running(&self) -> bool {
+        self.status.read().is_ok_and(|status| *status == WorkerStatus::Running)
+    }
+    /// Leaves the threads alone if they already run with `priority`
+    pub fn set_priority(&self, priority: i32) -> scheduler::Result<()>{
+        let mut applied_priority = self.applied_priority.lock().unwrap();
+        if *applied_priority == Some(priority) { return Ok(()) }
         (&self.workers).iter().try_for_each(|worker| worker.set_priority(priority))?;
+        *applied_priority = Some(priority);
+        self.failure_reported.store(false, Ordering::Relaxed);
         Ok(())
     }
+    pub fn applied_priority(&self) -> Option<i32> {
+        *self.applied_priority.lock().unwrap()
+    }
+    pub fn terminate(&mut self) {
+        match self.status.write() {
+            Ok(mut status) => *status = WorkerStatus::Terminated,
+            Err(poisoned) => *poisoned.into_inner() = WorkerStatus::Terminated,
+        }
+        self.workers.iter_mut().for_each(|worker| worker.join());
+    }
+}
+
+impl Drop for WorkerGroup {
+    fn drop(&mut self) {
+        self.terminate();
+    }
 }
 
 #[derive(Clone)]
 pub struct Worker {
-    image_data: Arc<MainImageData>,
     status: Arc<RwLock<WorkerStatus>>,
+    stats: Arc<GroupStats>,
+    control: Arc<GroupControl>,
     worker_thread: Option<Arc<thread::JoinHandle<()>>>,
     pid: u32
 }
 
 impl Worker {
-    pub fn new(image_data: Arc<MainImageData>, status: Arc<RwLock<WorkerStatus>>) -> Worker {
-        Worker { image_data, status, worker_thread: None, pid: 0 }
+    pub fn new(status: Arc<RwLock<WorkerStatus>>, stats: Arc<GroupStats>, control: Arc<GroupControl>) -> Worker {
+        Worker { status, stats, control, worker_thread: None, pid: 0 }
     }
 
-    fn spawn(&mut self, color: Color) {
+    fn spawn(&mut self, workload: Box<dyn Workload>, painter: Painter) {
         let (tx, rx) = mpsc::channel();
         let other_thread_self = self.clone();
 
-        self.worker_thread = Some(Arc::new(thread::spawn(move || unsafe {other_thread_self.handle(tx, color)})));
+        self.worker_thread = Some(Arc::new(thread::spawn(move || other_thread_self.handle(tx, workload, painter))));
         self.pid = rx.recv().expect("Couldn't receive");
     }
 
-    unsafe fn handle(self, tx: mpsc::Sender<u32>, color: Color) {
-        tx.send(unsafe {GetCurrentThreadId()} ).expect("Couldn't get pid of a thread");
+    fn handle(self, tx: mpsc::Sender<u32>, mut workload: Box<dyn Workload>, painter: Painter) {
+        tx.send(scheduler::current_thread_id()).expect("Couldn't get pid of a thread");
+        pi_lock::register_thread(self.control.locks.clone());
 
-        self.wait_for_ready();
-        
-        let mut pos: [i32; 2];
+        if !self.wait_for_ready() { return }
+        let batch_size = workload.batch_size();
 
+        let mut batch_start = Instant::now();
+        let mut charge_interval = batch_size;
+        let (mut uncharged_walks, mut uncharged_progress) = (0, 0);
         for i in 0.. {
-            if i % 1000 == 0 { self.wait_for_ready() }
-
-            pos = self.get_random_pos();
-
-            for _ in 0..1000 {
-                self.move_random_direction(&mut pos);
-                let stop = unsafe { self.fill_if_near_neighbor(&pos, color) };
-                if stop { break }
+            if uncharged_walks >= charge_interval {
+                self.control.charge(std::mem::take(&mut uncharged_walks) as u64, std::mem::take(&mut uncharged_progress));
+                // Waiting isn't working, the throttle only counts the rest of the batch
+                batch_start += self.wait_for_tokens();
+            }
+            if i % batch_size == 0 {
+                self.throttle(batch_start.elapsed());
+                if !self.wait_for_ready() { return }
+                charge_interval = self.control.charge_interval(batch_size);
+                batch_start = Instant::now();
             }
+
+            let progress = workload.work(&painter);
+            workload.draw(&painter);
+            self.stats.record(progress);
+            uncharged_walks += 1;
+            uncharged_progress += progress;
+            workload.report(&self.stats);
         }
     }
 
-    fn get_random_pos(&self) -> [i32; 2] {
-        [
-            rand::random_range(0..self.image_data.width()),
-            rand::random_range(0..self.image_data.height())
-        ]
+    fn join(&mut self) {
+        let Some(worker_thread) = self.worker_thread.take() else { return };
+        // The thread's own copy of the worker is cloned before the handle is stored, so this is the only reference
+        if let Ok(worker_thread) = Arc::try_unwrap(worker_thread) && worker_thread.join().is_err() {
+            log::error!("Worker thread {} panicked", self.pid);
+        }
     }
 
-    fn set_priority(&self, priority: i32) -> core::Result<()> {
-        set_thread_priority(priority, self.pid)?;
+    fn set_priority(&self, priority: i32) -> scheduler::Result<()> {
+        log::debug!("Set {} to priority {}", self.pid, priority);
+        scheduler::set_thread_priority(priority, self.pid)?;
         Ok(())
     }
 
-    fn move_random_direction(&self, pos: &mut [i32; 2]) {
-        let direction = rand::random_range(0..4);
-        let (x_dir, y_dir) = match direction {
-            0 => ( 1,  0),
-            1 => (-1,  0),
-            2 => ( 0,  1),
-            3 => ( 0, -1),
-            _ => panic!("Random range for a step direction has been setup wrongfully")
-        }; 
-        (pos[0], pos[1]) = (pos[0] + x_dir, pos[1] + y_dir);
+    fn cpu_time(&self) -> scheduler::Result<Duration> {
+        scheduler::thread_cpu_time(self.pid)
     }
 
-    fn hit_wall(&self, pos: &[i32; 2]) -> bool {
-        pos[0] == self.image_data.width() - 1 ||
-        pos[0] == 1 ||
-        pos[1] == self.image_data.height() - 1 ||
-        pos[1] == 1
+    /// Sleeps long enough after a batch that took `worked` for the group's throttle to hold
+    fn throttle(&self, worked: Duration) {
+        let throttle = self.control.throttle();
+        if throttle <= 0. { return }
+        // Up to 19 times the batch at the highest throttle, so it's slept in slices
+        let mut remaining = worked.mul_f64(throttle / (1. - throttle));
+        while !remaining.is_zero() {
+            let slice = remaining.min(MAX_SLEEP_SLICE);
+            thread::sleep(slice);
+            remaining -= slice;
+            if self.is_terminated() { return }
+        }
     }
 
-    unsafe fn fill_if_near_neighbor(&self, pos: &[i32; 2], color: Color) -> bool {
-        for (x_bias, y_bias) in [(1,0), (-1,0), (0,1), (0,-1)] {
-
-            let neighboring_color = unsafe { self.get_color(pos[0] + x_bias, pos[1] + y_bias) };
-            if neighboring_color == color {
-                unsafe { self.set_color(pos[0], pos[1], color) };
-                return true;
-            }
-        }
-        false
+    fn is_terminated(&self) -> bool {
+        self.status.read().is_ok_and(|status| *status == WorkerStatus::Terminated)
     }
 
-    fn truncate_pos(&self, pos: &[i32; 2]) -> [i32; 2] {
-        let mut new_pos = [0, 0];
-        let (a, b) = (pos[0] - 1, (self.image_data.width() - 1));
-        new_pos[0] = ((a % b) + b) % b;
-        let (a, b) = (pos[1] - 1, (self.image_data.height() - 1));
-        new_pos[1] = ((a % b) + b) % b;
-        
-        new_pos
+    /// Blocks while the group's token bucket is in debt. Returns how long that took
+    fn wait_for_tokens(&self) -> Duration {
+        let start = Instant::now();
+        loop {
+            let wait = self.control.token_wait();
+            if wait.is_zero() { break }
+            thread::sleep(wait.min(MAX_SLEEP_SLICE));
+            if self.is_terminated() { break }
+        }
+        start.elapsed()
     }
 
-    fn wait_for_ready(&self) {
+    /// Blocks while the group is idle. Returns false once the worker has to exit
+    fn wait_for_ready(&self) -> bool {
         loop {
             // If we run this function too fast,
             // it will die and never give permission to status
@@ -232,52 +783,19 @@ impl Worker {
                     continue;
                 }
             };
-            if *status == WorkerStatus::Idle {
-                thread::sleep(Duration::from_millis(100));
-                continue;
+            match *status {
+                WorkerStatus::Idle => {
+                    drop(status);
+                    thread::sleep(Duration::from_millis(100));
+                    continue;
+                }
+                WorkerStatus::Running => return true,
+                WorkerStatus::Terminated => return false,
             }
-            return;
-        }
-    }
-
-    unsafe fn get_color(&self, x: i32, y: i32) -> Color {
-        let pos = self.truncate_pos(&[x, y]);
-        unsafe { self.get_color_from_index(self.get_index(pos[0], pos[1])) }
-    }
-
-    unsafe fn set_color(&self, x: i32, y: i32, color: Color) {
-        let pos = self.truncate_pos(&[x, y]);
-        unsafe { self.set_color_from_index(self.get_index(pos[0], pos[1]), color) }
-    }
-
-    unsafe fn get_color_from_index(&self, index: usize) -> Color {
-        let img_ptr = self.image_data.data_ptr as *mut u8;
-        
-        unsafe {Color(
-            *img_ptr.add(index+0),
-            *img_ptr.add(index+1),
-            *img_ptr.add(index+2),
-            *img_ptr.add(index+3)
-        )}
-    }
-
-    unsafe fn set_color_from_index(&self, index: usize, color: Color) {
-        let img_ptr = self.image_data.data_ptr as *mut u8;
-
-        unsafe {
-            *img_ptr.add(index+0) = color.0;
-            *img_ptr.add(index+1) = color.1;
-            *img_ptr.add(index+2) = color.2;
-            *img_ptr.add(index+3) = color.3;
         }
     }
-
-    fn get_index(&self, x: i32, y: i32) -> usize {
-        4 * (x as usize + y as usize * self.image_data.width() as usize)
-    }
 }
 
-
 pub struct DeletionHandler {
 
 }
@@ -296,4 +814,64 @@ pub fn random_color() -> Color {
         rng.random_range(0..=255),
         255
     )
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limited(per_second: f64, unit: RateUnit) -> GroupControl {
+        let control = GroupControl::default();
+        control.set_rate_limit(Some(RateLimit { per_second, unit }));
+        control
+    }
+
+    #[test]
+    fn unlimited_groups_never_wait() {
+        let control = GroupControl::default();
+        assert_eq!(control.charge_interval(1000), 1000);
+        control.charge(1_000_000, 1_000_000);
+        assert_eq!(control.token_wait(), Duration::ZERO);
+    }
+
+    #[test]
+    fn charge_interval_is_a_burst_within_a_batch() {
+        assert_eq!(limited(100., RateUnit::Walks).charge_interval(1000), 10);
+        assert_eq!(limited(100_000., RateUnit::Walks).charge_interval(1000), 1000);
+        assert_eq!(limited(1., RateUnit::Walks).charge_interval(1000), 1);
+    }
+
+    #[test]
+    fn debt_is_waited_off_at_the_limit_rate() {
+        let control = limited(100., RateUnit::Walks);
+        control.charge(10, 0);
+        let wait = control.token_wait();
+        assert!(wait > Duration::from_millis(90) && wait <= Duration::from_millis(100), "{wait:?}");
+    }
+
+    #[test]
+    fn limit_counts_its_unit() {
+        let control = limited(100., RateUnit::Progress);
+        control.charge(1000, 0);
+        assert_eq!(control.token_wait(), Duration::ZERO);
+        control.charge(0, 10);
+        assert!(control.token_wait() > Duration::ZERO);
+    }
+
+    #[test]
+    fn refill_is_capped_at_a_burst() {
+        let control = limited(100., RateUnit::Walks);
+        control.bucket.lock().unwrap().refilled -= Duration::from_secs(10);
+        assert_eq!(control.token_wait(), Duration::ZERO);
+        // Ten seconds of idling only saved up a burst of 10 walks
+        control.charge(20, 0);
+        assert!(control.token_wait() > Duration::from_millis(90));
+    }
+
+    #[test]
+    fn new_limit_forgives_debt() {
+        let control = limited(1., RateUnit::Walks);
+        control.charge(100, 0);
+        control.set_rate_limit(Some(RateLimit { per_second: 1., unit: RateUnit::Walks }));
+        assert_eq!(control.token_wait(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,101 @@
+//! Mutex with priority inheritance: while a thread waits for the lock, the holder runs with the waiter's priority.
+//! On Linux it's backed by PI futexes, where the kernel lends real-time priorities to the holder.
+//! Nice levels and the throttles of the share controller aren't lent by the kernel, there the main controller
+//! lends the holder group the waiters' priority itself, see [`LockCounters`]. On Linux that takes CAP_SYS_NICE,
+//! without it the main controller holds back the groups the holder should have run above
+
+use std::cell::{RefCell, UnsafeCell};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+#[cfg(target_os = "linux")]
+mod futex;
+#[cfg(target_os = "linux")]
+use futex::RawLock;
+
+#[cfg(not(target_os = "linux"))]
+mod fallback;
+#[cfg(not(target_os = "linux"))]
+use fallback::RawLock;
+
+/// Threads of a group holding or waiting for a priority inheritance lock
+#[derive(Default)]
+pub struct LockCounters {
+    holding: AtomicU32,
+    waiting: AtomicU32,
+}
+
+impl LockCounters {
+    pub fn holding(&self) -> u32 {self.holding.load(Ordering::Relaxed)}
+    pub fn waiting(&self) -> u32 {self.waiting.load(Ordering::Relaxed)}
+}
+
+thread_local! {
+    static THREAD_COUNTERS: RefCell<Option<Arc<LockCounters>>> = const { RefCell::new(None) };
+}
+
+/// Makes the locks the calling thread takes count towards `counters`
+pub fn register_thread(counters: Arc<LockCounters>) {
+    THREAD_COUNTERS.with(|thread_counters| *thread_counters.borrow_mut() = Some(counters));
+}
+
+fn count(update: impl FnOnce(&LockCounters)) {
+    THREAD_COUNTERS.with(|thread_counters| {
+        if let Some(counters) = thread_counters.borrow().as_ref() { update(counters) }
+    });
+}
+
+pub struct PiMutex<T> {
+    raw: RawLock,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for PiMutex<T> {}
+unsafe impl<T: Send> Sync for PiMutex<T> {}
+
+impl<T> PiMutex<T> {
+    pub const fn new(value: T) -> PiMutex<T> {
+        PiMutex { raw: RawLock::new(), value: UnsafeCell::new(value) }
+    }
+
+    pub fn lock(&self) -> PiMutexGuard<'_, T> {
+        if !self.raw.try_lock() {
+            count(|counters| {counters.waiting.fetch_add(1, Ordering::Relaxed);});
+            self.raw.lock();
+            count(|counters| {counters.waiting.fetch_sub(1, Ordering::Relaxed);});
+        }
+        count(|counters| {counters.holding.fetch_add(1, Ordering::Relaxed);});
+        PiMutexGuard { mutex: self, not_send: PhantomData }
+    }
+}
+
+/// Has to be dropped on the thread that locked, the futex is owned by the thread id
+/// and the counters belong to the thread's group
+pub struct PiMutexGuard<'a, T> {
+    mutex: &'a PiMutex<T>,
+    not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: Sync> Sync for PiMutexGuard<'_, T> {}
+
+impl<T> Deref for PiMutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for PiMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> Drop for PiMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        count(|counters| {counters.holding.fetch_sub(1, Ordering::Relaxed);});
+        self.mutex.raw.unlock();
+    }
+}
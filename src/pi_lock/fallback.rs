@@ -0,0 +1,31 @@
+use std::sync::{Condvar, Mutex};
+
+/// Without PI futexes the operating system doesn't lend priorities, only the share controller does
+pub struct RawLock {
+    locked: Mutex<bool>,
+    released: Condvar,
+}
+
+impl RawLock {
+    pub const fn new() -> RawLock {
+        RawLock { locked: Mutex::new(false), released: Condvar::new() }
+    }
+
+    pub fn try_lock(&self) -> bool {
+        let mut locked = self.locked.lock().unwrap();
+        !std::mem::replace(&mut *locked, true)
+    }
+
+    pub fn lock(&self) {
+        let mut locked = self.locked.lock().unwrap();
+        while *locked {
+            locked = self.released.wait(locked).unwrap();
+        }
+        *locked = true;
+    }
+
+    pub fn unlock(&self) {
+        *self.locked.lock().unwrap() = false;
+        self.released.notify_one();
+    }
+}
@@ -0,0 +1,54 @@
+use std::io;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// PI futex word: 0 when free, otherwise the holder's thread id, with `FUTEX_WAITERS` set by the kernel
+/// once someone blocks. Uncontended locking and unlocking never enter the kernel
+pub struct RawLock {
+    futex: AtomicU32,
+}
+
+fn current_thread_id() -> u32 {
+    unsafe { libc::gettid() as u32 }
+}
+
+impl RawLock {
+    pub const fn new() -> RawLock {
+        RawLock { futex: AtomicU32::new(0) }
+    }
+
+    pub fn try_lock(&self) -> bool {
+        self.futex.compare_exchange(0, current_thread_id(), Ordering::Acquire, Ordering::Relaxed).is_ok()
+    }
+
+    pub fn lock(&self) {
+        if self.try_lock() { return }
+        while self.futex_op(libc::FUTEX_LOCK_PI) == -1 {
+            let error = io::Error::last_os_error();
+            match error.raw_os_error() {
+                Some(libc::EINTR) | Some(libc::EAGAIN) => continue,
+                _ => panic!("Couldn't lock a PI futex: {error}"),
+            }
+        }
+    }
+
+    pub fn unlock(&self) {
+        // Only fails when the kernel marked the word as having waiters, then it has to pick the next holder
+        if self.futex.compare_exchange(current_thread_id(), 0, Ordering::Release, Ordering::Relaxed).is_ok() { return }
+        if self.futex_op(libc::FUTEX_UNLOCK_PI) == -1 {
+            panic!("Couldn't unlock a PI futex: {}", io::Error::last_os_error());
+        }
+    }
+
+    fn futex_op(&self, op: i32) -> libc::c_long {
+        unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                self.futex.as_ptr(),
+                op | libc::FUTEX_PRIVATE_FLAG,
+                0,
+                ptr::null::<libc::timespec>(),
+            )
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::interface;
+use crate::main_controller::{MainController, MainImageData};
+use crate::stats::format_si;
+use crate::tournament::Tournament;
+use crate::workload::WorkloadKind;
+use crate::{PrioritiesContainer, ProgramState};
+
+/// How often the end conditions are checked while the race runs, in seconds.
+/// Counting territory scans the whole canvas, so it isn't done every frame
+const CHECK_PERIOD: f32 = 0.25;
+
+/// Ends the race once one of the configured conditions triggers and shows who won
+pub struct RacePlugin;
+impl Plugin for RacePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Race>()
+            .add_systems(Update, (track_race, dismiss_results).run_if(in_state(ProgramState::Running)));
+    }
+}
+
+/// Any condition that is set ends the race, whichever triggers first
+#[derive(Clone, Debug, Default)]
+pub struct EndConditions {
+    /// Percentage of the free canvas painted by all groups together
+    pub fill_percent: Option<f64>,
+    /// Pixels a single group has to paint
+    pub group_pixels: Option<u64>,
+    /// A cluster reaching any edge of the canvas
+    pub touch_edge: bool,
+    /// Time spent running, pauses don't count
+    pub time_limit: Option<Duration>,
+}
+
+impl EndConditions {
+    pub fn is_empty(&self) -> bool {
+        self.fill_percent.is_none() && self.group_pixels.is_none() && !self.touch_edge && self.time_limit.is_none()
+    }
+
+    /// Why the race is over, `None` while it goes on. Tile rendering always ends once no tile is left
+    fn check(&self, main_controller: &MainController, main_image_data: &MainImageData, elapsed: Duration) -> Option<String> {
+        let rendering = (0..main_controller.group_amount()).any(|group_index| main_controller.workload(group_index) == WorkloadKind::TileRender);
+        if rendering && main_controller.tiles().is_some_and(|tiles| tiles.is_finished()) {
+            return Some("All tiles rendered".to_string());
+        }
+        if let Some(time_limit) = self.time_limit && elapsed >= time_limit {
+            return Some(format!("Time limit of {:.0}s reached", time_limit.as_secs_f64()));
+        }
+        if self.touch_edge {
+            let (width, height) = (main_image_data.width(), main_image_data.height());
+            for group_index in 0..main_controller.group_amount() {
+                let Some((min, max)) = main_controller.cluster_bounds(group_index) else { continue };
+                if min[0] <= 0 || min[1] <= 0 || max[0] >= width - 1 || max[1] >= height - 1 {
+                    return Some(format!("G{} touched the edge", group_index));
+                }
+            }
+        }
+        if self.fill_percent.is_none() && self.group_pixels.is_none() { return None }
+
+        let territory = main_controller.territory();
+        if let Some(group_pixels) = self.group_pixels
+            && let Some(group_index) = territory.iter().position(|pixels| *pixels >= group_pixels) {
+            return Some(format!("G{} painted {} pixels", group_index, format_si(group_pixels as f64)));
+        }
+        if let Some(fill_percent) = self.fill_percent {
+            let free_pixels = main_image_data.canvas().free_pixels().max(1);
+            if 100. * territory.iter().sum::<u64>() as f64 / free_pixels as f64 >= fill_percent {
+                return Some(format!("Canvas {:.0}% filled", fill_percent));
+            }
+        }
+        None
+    }
+}
+
+/// How one group did, collected when the race ends
+#[derive(Clone, Debug)]
+pub struct GroupResult {
+    pub group_index: usize,
+    pub pixels: u64,
+    pub progress: u64,
+    pub cpu_time: Duration,
+    pub priority: Option<i32>,
+}
+
+/// Groups ranked by painted pixels, the winner first
+pub fn collect_results(main_controller: &MainController, priorities: &[i32]) -> Vec<GroupResult> {
+    let mut results: Vec<GroupResult> = main_controller.territory().into_iter().enumerate()
+        .map(|(group_index, pixels)| GroupResult {
+            group_index,
+            pixels,
+            progress: main_controller.stats(group_index).progress(),
+            cpu_time: main_controller.cpu_time(group_index),
+            priority: priorities.get(group_index).copied(),
+        })
+        .collect();
+    results.sort_by_key(|result| std::cmp::Reverse(result.pixels));
+    results
+}
+
+/// Running time of the current race
+#[derive(Resource, Default)]
+pub struct Race {
+    elapsed: Duration,
+    since_check: f32,
+    finished: bool,
+}
+
+/// Covers the window until it is clicked
+#[derive(Component, Debug, Default, Clone)]
+struct ResultsOverlay;
+
+type ClickedOverlay = (Changed<Interaction>, With<ResultsOverlay>);
+
+/// Where the end conditions come from, the config or the tournament in progress
+#[derive(SystemParam)]
+struct RaceRules<'w> {
+    config: Res<'w, Config>,
+    tournament: ResMut<'w, Tournament>,
+}
+
+/// Shows the results of a race and takes them down again
+#[derive(SystemParam)]
+struct ResultsOverlays<'w, 's> {
+    commands: Commands<'w, 's>,
+    asset_server: Res<'w, AssetServer>,
+    overlays: Query<'w, 's, Entity, With<ResultsOverlay>>,
+}
+
+impl ResultsOverlays<'_, '_> {
+    fn show(&mut self, lines: Vec<OverlayLine>) {
+        spawn_overlay(&mut self.commands, lines, self.asset_server.load(interface::FONT_PATH));
+    }
+
+    fn clear(&mut self) {
+        self.overlays.iter().for_each(|overlay| self.commands.entity(overlay).despawn());
+    }
+}
+
+fn track_race(
+    time: Res<Time>,
+    main_controller: Res<MainController>,
+    main_image_data: Res<MainImageData>,
+    priorities_container: Res<PrioritiesContainer>,
+    mut race: ResMut<Race>,
+    mut rules: RaceRules,
+    mut overlays: ResultsOverlays,
+) {
+    // Resetting, resizing or swapping a workload starts a new race
+    if main_controller.is_changed() {
+        *race = Race::default();
+        overlays.clear();
+    }
+    if race.finished || !main_controller.is_running() { return }
+
+    race.elapsed += time.delta();
+    race.since_check += time.delta_secs();
+    if race.since_check < CHECK_PERIOD { return }
+    race.since_check = 0.;
+
+    let Some(reason) = rules.tournament.end_conditions(&rules.config).check(&main_controller, &main_image_data, race.elapsed) else { return };
+    race.finished = true;
+    main_controller.stop_all().unwrap();
+
+    let results = collect_results(&main_controller, &priorities_container.priorities);
+    if rules.tournament.is_active() {
+        rules.tournament.record(results);
+    } else {
+        overlays.show(race_lines(&main_controller, &results, &reason, race.elapsed));
+    }
+}
+
+/// Text, font size and color of one line of an overlay
+pub type OverlayLine = (String, f32, Color);
+
+pub const OVERLAY_TITLE_COLOR: Color = Color::srgb(0.9, 0.9, 0.9);
+pub const OVERLAY_NOTE_COLOR: Color = Color::srgb(0.7, 0.7, 0.7);
+
+fn race_lines(main_controller: &MainController, results: &[GroupResult], reason: &str, elapsed: Duration) -> Vec<OverlayLine> {
+    let total_cpu_time = results.iter().map(|result| result.cpu_time).sum::<Duration>().as_secs_f64().max(f64::EPSILON);
+
+    let mut lines = vec![
+        (reason.to_string(), 32., OVERLAY_TITLE_COLOR),
+        (format!("after {:.1}s of running", elapsed.as_secs_f64()), 20., OVERLAY_NOTE_COLOR),
+    ];
+    for (rank, result) in results.iter().enumerate() {
+        let workload = main_controller.workload(result.group_index);
+        lines.push((
+            format!(
+                "#{}  G{}  {} px  {}/s {}  CPU {:.1}s ({:.0}%) at priority {}",
+                rank + 1,
+                result.group_index,
+                format_si(result.pixels as f64),
+                format_si(result.progress as f64 / elapsed.as_secs_f64().max(f64::EPSILON)),
+                workload.metric(),
+                result.cpu_time.as_secs_f64(),
+                100. * result.cpu_time.as_secs_f64() / total_cpu_time,
+                result.priority.map_or("-".to_string(), |priority| priority.to_string()),
+            ),
+            24.,
+            main_controller.color(result.group_index).into(),
+        ));
+    }
+    lines
+}
+
+/// Covers the window with a panel listing the lines, until it is clicked
+pub fn spawn_overlay(commands: &mut Commands, lines: Vec<OverlayLine>, font: Handle<Font>) {
+    commands.spawn((
+        ResultsOverlay,
+        Button,
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        GlobalZIndex(10),
+        BackgroundColor(Color::srgba(0., 0., 0., 0.6)),
+    )).with_children(|overlay| {
+        overlay.spawn((
+            Node {
+                padding: UiRect::all(Val::Px(30.)),
+                row_gap: Val::Px(8.),
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            BorderRadius::all(Val::Px(10.)),
+            BackgroundColor(Color::srgb(0.16, 0.16, 0.18)),
+        )).with_children(|panel| {
+            for (text, font_size, color) in lines.into_iter().chain([("Click to close".to_string(), 16., OVERLAY_NOTE_COLOR)]) {
+                panel.spawn((
+                    Text::new(text),
+                    TextFont { font: font.clone(), font_size, ..default() },
+                    TextColor(color),
+                ));
+            }
+        });
+    });
+}
+
+fn dismiss_results(
+    mut commands: Commands,
+    overlays: Query<(Entity, &Interaction), ClickedOverlay>,
+) {
+    for (overlay, interaction) in overlays {
+        if *interaction == Interaction::Pressed {
+            commands.entity(overlay).despawn();
+        }
+    }
+}
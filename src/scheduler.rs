@@ -0,0 +1,12 @@
+//! Per-thread priorities and CPU time, backed by `SetThreadPriority` on Windows and nice values on Linux.
+//! Both backends take the thread ids returned by [`current_thread_id`] and priorities in the slider range
+
+#[cfg(windows)]
+mod win32;
+#[cfg(windows)]
+pub use win32::*;
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "linux")]
+pub use linux::*;
@@ -0,0 +1,59 @@
+use std::fs;
+use std::io;
+use std::time::Duration;
+
+pub type Result<T> = io::Result<T>;
+pub type Error = io::Error;
+
+/// Nice levels between two neighboring slider positions
+const NICE_PER_PRIORITY: i32 = 5;
+/// Slider priority that maps to nice 0. Unprivileged processes can only make threads nicer,
+/// so the top of the slider is the default nice value instead of the middle
+const TOP_PRIORITY: i32 = 2;
+
+pub fn current_thread_id() -> u32 {
+    unsafe { libc::gettid() as u32 }
+}
+
+/// Nice value a slider priority gets, a higher priority is less nice
+pub fn nice_for_priority(priority: i32) -> Option<i32> {
+    Some((NICE_PER_PRIORITY * (TOP_PRIORITY - priority)).clamp(-20, 19))
+}
+
+/// Linux keeps a nice value per thread, addressed by its id.
+/// Lowering the nice value again needs CAP_SYS_NICE, without it this fails with a permission error
+pub fn set_thread_priority(priority: i32, thread_id: u32) -> Result<()> {
+    let nice = nice_for_priority(priority).unwrap_or_default();
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, thread_id, nice) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Restricts the thread to the first CPU the calling thread may run on, or to all of them again
+pub fn pin_thread(pinned: bool, thread_id: u32) -> Result<()> {
+    let mut cpus: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    if unsafe { libc::sched_getaffinity(0, size_of::<libc::cpu_set_t>(), &mut cpus) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    if pinned {
+        let first_cpu = (0..libc::CPU_SETSIZE as usize).find(|cpu| unsafe { libc::CPU_ISSET(*cpu, &cpus) });
+        unsafe { libc::CPU_ZERO(&mut cpus) };
+        if let Some(first_cpu) = first_cpu {
+            unsafe { libc::CPU_SET(first_cpu, &mut cpus) };
+        }
+    }
+    if unsafe { libc::sched_setaffinity(thread_id as libc::pid_t, size_of::<libc::cpu_set_t>(), &cpus) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Time spent on a CPU, as accounted by the scheduler
+pub fn thread_cpu_time(thread_id: u32) -> Result<Duration> {
+    let schedstat = fs::read_to_string(format!("/proc/self/task/{thread_id}/schedstat"))?;
+    let nanos = schedstat.split_whitespace().next()
+        .and_then(|nanos| nanos.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed schedstat"))?;
+    Ok(Duration::from_nanos(nanos))
+}
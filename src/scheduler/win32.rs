@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use windows::Win32::Foundation::{CloseHandle, FILETIME};
+use windows::Win32::System::Threading::{
+    GetCurrentProcess, GetCurrentThreadId, GetProcessAffinityMask, GetThreadTimes, OpenThread, SetThreadAffinityMask,
+    SetThreadPriority, THREAD_QUERY_INFORMATION, THREAD_QUERY_LIMITED_INFORMATION, THREAD_SET_INFORMATION, THREAD_PRIORITY
+};
+
+pub type Result<T> = windows::core::Result<T>;
+pub type Error = windows::core::Error;
+
+pub fn current_thread_id() -> u32 {
+    unsafe { GetCurrentThreadId() }
+}
+
+/// Slider priorities map directly onto the THREAD_PRIORITY levels
+pub fn set_thread_priority(priority: i32, thread_id: u32) -> Result<()> {
+    let thread_handle = unsafe { OpenThread(THREAD_SET_INFORMATION, false, thread_id)? };
+    unsafe { SetThreadPriority(thread_handle, THREAD_PRIORITY(priority))? };
+    Ok(())
+}
+
+/// Restricts the thread to the first processor the process may run on, or to all of them again
+pub fn pin_thread(pinned: bool, thread_id: u32) -> Result<()> {
+    let (mut process_mask, mut system_mask) = (0, 0);
+    unsafe { GetProcessAffinityMask(GetCurrentProcess(), &mut process_mask, &mut system_mask)? };
+    // The lowest bit set is the first processor
+    let mask = if pinned { process_mask & process_mask.wrapping_neg() } else { process_mask };
+
+    let thread_handle = unsafe { OpenThread(THREAD_SET_INFORMATION | THREAD_QUERY_INFORMATION, false, thread_id)? };
+    let previous_mask = unsafe { SetThreadAffinityMask(thread_handle, mask) };
+    let error = windows::core::Error::from_thread();
+    unsafe { CloseHandle(thread_handle)? };
+    if previous_mask == 0 { return Err(error) }
+    Ok(())
+}
+
+/// Processor time in user and kernel mode
+pub fn thread_cpu_time(thread_id: u32) -> Result<Duration> {
+    let thread_handle = unsafe { OpenThread(THREAD_QUERY_LIMITED_INFORMATION, false, thread_id)? };
+    let (mut creation, mut exit, mut kernel, mut user) = Default::default();
+    let times = unsafe { GetThreadTimes(thread_handle, &mut creation, &mut exit, &mut kernel, &mut user) };
+    unsafe { CloseHandle(thread_handle)? };
+    times?;
+    Ok(filetime_duration(kernel) + filetime_duration(user))
+}
+
+/// FILETIME intervals count 100ns ticks
+fn filetime_duration(time: FILETIME) -> Duration {
+    let ticks = (time.dwHighDateTime as u64) << 32 | time.dwLowDateTime as u64;
+    Duration::from_nanos(ticks * 100)
+}
+
+/// Windows doesn't share time by weight, a runnable thread of higher priority always goes first
+pub fn nice_for_priority(_priority: i32) -> Option<i32> {
+    None
+}
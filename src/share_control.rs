@@ -0,0 +1,282 @@
+use bevy::prelude::*;
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::interface;
+use crate::main_controller::MainController;
+use crate::share_model;
+use crate::stats::StatsPanel;
+use crate::{PrioritiesContainer, ProgramState, GROUP_AMOUNT};
+
+/// How often the feedback controller measures and adjusts, in seconds
+const CONTROL_PERIOD: f32 = 0.5;
+
+/// Controller gains, errors are differences of shares between 0 and 1
+const PROPORTIONAL_GAIN: f64 = 4.;
+const INTEGRAL_GAIN: f64 = 2.;
+const DERIVATIVE_GAIN: f64 = 0.2;
+/// Bound on the integrated error, so a target that can't be reached doesn't wind the controller up forever
+const INTEGRAL_LIMIT: f64 = 2.;
+/// Weight of the newest measurement, the rest is the previous estimate
+const SMOOTHING: f64 = 0.5;
+
+const LOWEST_PRIORITY: i32 = -2;
+const HIGHEST_PRIORITY: i32 = 2;
+/// Effort below the lowest priority it takes to throttle a group completely
+const THROTTLE_SPAN: f64 = 2.;
+
+/// Samples kept for the convergence plot
+const HISTORY_LENGTH: usize = 60;
+const PLOT_WIDTH: f32 = 300.;
+const PLOT_HEIGHT: f32 = 80.;
+
+/// Lets the sliders set target shares of throughput that a feedback controller tracks,
+/// instead of setting priorities directly
+pub struct ShareControlPlugin;
+impl Plugin for ShareControlPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<ControlMode>()
+            .add_systems(OnEnter(ProgramState::Running), setup_share_plot)
+            .add_systems(Update, (control_mode_button_controller, run_share_control).run_if(in_state(ProgramState::Running)));
+    }
+}
+
+/// What the group sliders set
+#[derive(Resource, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ControlMode {
+    #[default]
+    Priorities,
+    TargetShares,
+}
+
+impl ControlMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ControlMode::Priorities => "Priorities",
+            ControlMode::TargetShares => "Target shares",
+        }
+    }
+}
+
+/// Slider positions as target shares. Every step up the slider is worth one more part,
+/// so -1, 0 and 2 ask for 20%, 30% and 50%
+pub fn target_shares(slider_values: &[i32]) -> Vec<f64> {
+    let parts: Vec<f64> = slider_values.iter().map(|value| (value - LOWEST_PRIORITY + 1) as f64).collect();
+    share_model::shares(&parts)
+}
+
+/// Priority a controller effort maps to
+pub fn priority_for_effort(effort: f64) -> i32 {
+    (effort.round() as i32).clamp(LOWEST_PRIORITY, HIGHEST_PRIORITY)
+}
+
+/// Once the lowest priority isn't low enough, the group gets throttled
+pub fn throttle_for_effort(effort: f64) -> f64 {
+    ((LOWEST_PRIORITY as f64 - effort) / THROTTLE_SPAN).clamp(0., 1.)
+}
+
+/// PID controller per group, turning the error between target and measured share of throughput into an effort.
+/// Efforts above zero ask for more CPU time, see [`priority_for_effort`] and [`throttle_for_effort`]
+#[derive(Default)]
+pub struct ShareControl {
+    targets: Option<Vec<f64>>,
+    last_progress: Vec<u64>,
+    measured: Vec<f64>,
+    integral: Vec<f64>,
+    last_error: Vec<f64>,
+    history: VecDeque<Vec<f64>>,
+}
+
+impl ShareControl {
+    pub fn targets(&self) -> Option<&[f64]> {self.targets.as_deref()}
+    /// Measured shares, oldest first
+    pub fn history(&self) -> &VecDeque<Vec<f64>> {&self.history}
+
+    /// Restarts the controller when turning it on or off, new targets alone keep its state
+    pub fn set_targets(&mut self, targets: Option<Vec<f64>>) {
+        if targets.is_none() != self.targets.is_none() {
+            let last_progress = std::mem::take(&mut self.last_progress);
+            *self = ShareControl { last_progress, ..default() };
+        }
+        self.targets = targets;
+    }
+
+    /// Measures the shares since the last step and returns each group's effort,
+    /// `None` while there are no targets or nothing ran
+    pub fn step(&mut self, progress: &[u64], elapsed: Duration) -> Option<Vec<f64>> {
+        let group_amount = progress.len();
+        self.last_progress.resize(group_amount, 0);
+        // Progress goes backwards when a group gets respawned
+        let deltas: Vec<f64> = progress.iter().zip(&self.last_progress)
+            .map(|(progress, last_progress)| progress.saturating_sub(*last_progress) as f64)
+            .collect();
+        self.last_progress = progress.to_vec();
+
+        let targets = self.targets.as_ref()?;
+        if deltas.iter().sum::<f64>() == 0. { return None }
+        let dt = elapsed.as_secs_f64().max(f64::EPSILON);
+
+        let shares = share_model::shares(&deltas);
+        if self.measured.len() != group_amount {
+            self.measured = shares;
+            self.integral = vec![0.; group_amount];
+            self.last_error = vec![0.; group_amount];
+        } else {
+            self.measured.iter_mut().zip(&shares).for_each(|(measured, share)| *measured += SMOOTHING * (share - *measured));
+        }
+
+        let efforts = (0..group_amount).map(|group_index| {
+            let error = targets[group_index] - self.measured[group_index];
+            self.integral[group_index] = (self.integral[group_index] + error * dt).clamp(-INTEGRAL_LIMIT, INTEGRAL_LIMIT);
+            let derivative = (error - self.last_error[group_index]) / dt;
+            self.last_error[group_index] = error;
+            PROPORTIONAL_GAIN * error + INTEGRAL_GAIN * self.integral[group_index] + DERIVATIVE_GAIN * derivative
+        }).collect();
+
+        self.history.push_back(self.measured.clone());
+        if self.history.len() > HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        Some(efforts)
+    }
+}
+
+#[derive(Component, Debug, Default, Clone)]
+struct SharePlot;
+
+/// Horizontal line at the group's target share
+#[derive(Component, Debug, Default, Clone)]
+struct TargetLine(usize);
+
+/// One measured share of a group, `sample` counts from the oldest in the history
+#[derive(Component, Debug, Default, Clone)]
+struct SharePoint {
+    group_index: usize,
+    sample: usize,
+}
+
+fn setup_share_plot(
+    mut commands: Commands,
+    panels: Query<Entity, With<StatsPanel>>,
+) {
+    for panel in panels {
+        commands.entity(panel).with_children(|panel| {
+            panel.spawn((
+                SharePlot,
+                Node {
+                    display: Display::None,
+                    width: Val::Px(PLOT_WIDTH),
+                    height: Val::Px(PLOT_HEIGHT),
+                    margin: UiRect::vertical(Val::Px(5.)),
+                    ..default()
+                },
+                BackgroundColor(Color::srgb(0.13, 0.13, 0.15)),
+            )).with_children(|plot| {
+                for group_index in 0..GROUP_AMOUNT as usize {
+                    plot.spawn((
+                        TargetLine(group_index),
+                        Node {
+                            position_type: PositionType::Absolute,
+                            width: Val::Percent(100.),
+                            height: Val::Px(1.),
+                            ..default()
+                        },
+                        BackgroundColor(Color::BLACK),
+                    ));
+                    for sample in 0..HISTORY_LENGTH {
+                        plot.spawn((
+                            SharePoint { group_index, sample },
+                            Node {
+                                display: Display::None,
+                                position_type: PositionType::Absolute,
+                                left: Val::Px(sample as f32 * PLOT_WIDTH / HISTORY_LENGTH as f32),
+                                width: Val::Px(3.),
+                                height: Val::Px(3.),
+                                ..default()
+                            },
+                            BackgroundColor(Color::BLACK),
+                        ));
+                    }
+                }
+            });
+        });
+    }
+}
+
+fn control_mode_button_controller(
+    main_controller: Res<MainController>,
+    mut control_mode: ResMut<ControlMode>,
+    mut priorities_container: ResMut<PrioritiesContainer>,
+    control_mode_button: Query<(&mut BackgroundColor, &Interaction), interface::ButtonInteraction<interface::ControlModeButton>>,
+    mut control_mode_labels: Query<&mut Text, With<interface::ControlModeLabel>>,
+) {
+    for (mut bg_color, interaction) in control_mode_button {
+        match *interaction {
+            Interaction::Pressed => {
+                *bg_color = interface::TOOL_BUTTON_PRESSED_COLOR.into();
+
+                *control_mode = match *control_mode {
+                    ControlMode::Priorities => ControlMode::TargetShares,
+                    ControlMode::TargetShares => {
+                        main_controller.set_share_targets(None);
+                        ControlMode::Priorities
+                    }
+                };
+                // Forget applied priorities so the sliders get pushed again, now meaning something else
+                priorities_container.priorities.clear();
+                control_mode_labels.iter_mut().for_each(|mut label| label.0 = control_mode.name().to_string());
+            }
+            Interaction::Hovered => {
+                *bg_color = interface::TOOL_BUTTON_HOVERED.into();
+            }
+            Interaction::None => {
+                *bg_color = interface::TOOL_BUTTON_IDLE_COLOR.into();
+            }
+        };
+    }
+}
+
+type PlotOnly = (With<SharePlot>, Without<TargetLine>, Without<SharePoint>);
+
+fn run_share_control(
+    time: Res<Time>,
+    control_mode: Res<ControlMode>,
+    main_controller: Res<MainController>,
+    mut since_step: Local<f32>,
+    mut plots: Query<&mut Node, PlotOnly>,
+    mut target_lines: Query<(&mut Node, &mut BackgroundColor, &TargetLine), Without<SharePoint>>,
+    mut points: Query<(&mut Node, &mut BackgroundColor, &SharePoint), Without<TargetLine>>,
+) {
+    *since_step += time.delta_secs();
+    if *since_step < CONTROL_PERIOD { return }
+    let elapsed = Duration::from_secs_f32(std::mem::take(&mut *since_step));
+
+    if let Err(error) = main_controller.step_share_control(elapsed) {
+        error!("Couldn't update priorities: {error}");
+    }
+
+    let active = *control_mode == ControlMode::TargetShares;
+    for mut node in &mut plots {
+        node.display = if active { Display::Flex } else { Display::None };
+    }
+    if !active { return }
+
+    let share_control = main_controller.share_control();
+    for (mut node, mut bg_color, line) in &mut target_lines {
+        let Some(target) = share_control.targets().and_then(|targets| targets.get(line.0)) else { continue };
+        node.top = Val::Px((1. - *target as f32) * PLOT_HEIGHT);
+        *bg_color = bevy::color::Color::from(main_controller.color(line.0)).with_alpha(0.5).into();
+    }
+    let history = share_control.history();
+    for (mut node, mut bg_color, point) in &mut points {
+        let Some(share) = history.get(point.sample).and_then(|shares| shares.get(point.group_index)) else {
+            node.display = Display::None;
+            continue;
+        };
+        node.display = Display::Flex;
+        node.top = Val::Px((1. - *share as f32) * (PLOT_HEIGHT - 3.));
+        *bg_color = main_controller.color(point.group_index).into();
+    }
+}
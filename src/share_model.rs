@@ -0,0 +1,112 @@
+//! Expected CPU shares under a weight based scheduler like Linux CFS, where a runnable thread gets CPU time
+//! in proportion to the weight of its nice value
+
+/// `sched_prio_to_weight` from the kernel, for nice -20 to 19. Every nice level is worth about 25% CPU time
+const NICE_TO_WEIGHT: [u32; 40] = [
+    88761, 71755, 56483, 46273, 36291,
+    29154, 23254, 18705, 14949, 11916,
+     9548,  7620,  6100,  4904,  3906,
+     3121,  2501,  1991,  1586,  1277,
+     1024,   820,   655,   526,   423,
+      335,   272,   215,   172,   137,
+      110,    87,    70,    56,    45,
+       36,    29,    23,    18,    15,
+];
+
+pub fn nice_weight(nice: i32) -> u32 {
+    NICE_TO_WEIGHT[(nice.clamp(-20, 19) + 20) as usize]
+}
+
+/// Share of the workers' total CPU time every group should get, given each group's nice value and worker count,
+/// assuming all workers are always runnable and nothing else competes for the CPUs.
+/// A thread can't use more than one CPU, so whatever heavy threads can't use is split among the lighter ones
+pub fn expected_shares(nice_values: &[i32], worker_counts: &[usize], cpus: usize) -> Vec<f64> {
+    // (group, weight) of every worker that still competes for the remaining capacity
+    let mut competing: Vec<(usize, f64)> = nice_values.iter().zip(worker_counts).enumerate()
+        .flat_map(|(group_index, (nice, workers))| (0..*workers).map(move |_| (group_index, nice_weight(*nice) as f64)))
+        .collect();
+    let mut group_cpus = vec![0.; nice_values.len()];
+    let mut capacity = cpus as f64;
+
+    loop {
+        let total_weight: f64 = competing.iter().map(|(_, weight)| weight).sum();
+        let saturated = competing.iter().position(|(_, weight)| weight / total_weight * capacity > 1.);
+        let Some(saturated) = saturated else { break };
+
+        let (group_index, _) = competing.swap_remove(saturated);
+        group_cpus[group_index] += 1.;
+        capacity -= 1.;
+    }
+    let total_weight: f64 = competing.iter().map(|(_, weight)| weight).sum();
+    for (group_index, weight) in &competing {
+        group_cpus[*group_index] += weight / total_weight * capacity;
+    }
+
+    shares(&group_cpus)
+}
+
+/// Each value as a fraction of their sum
+pub fn shares(values: &[f64]) -> Vec<f64> {
+    let total: f64 = values.iter().sum();
+    values.iter().map(|value| if total > 0. { value / total } else { 0. }).collect()
+}
+
+/// Jain's fairness index, 1 when all values are equal down to 1/n when one value takes everything
+pub fn jain_index(values: &[f64]) -> f64 {
+    let sum: f64 = values.iter().sum();
+    let sum_of_squares: f64 = values.iter().map(|value| value * value).sum();
+    if sum_of_squares == 0. { return 1. }
+    sum * sum / (values.len() as f64 * sum_of_squares)
+}
+
+/// Largest value over the smallest, infinite once a value drops to zero
+pub fn max_min_ratio(values: &[f64]) -> f64 {
+    let max = values.iter().copied().fold(0., f64::max);
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    if max == 0. { 1. } else { max / min }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+        }
+    }
+
+    #[test]
+    fn equal_groups_share_equally() {
+        assert_close(&expected_shares(&[0, 0, 0], &[4, 4, 4], 4), &[1. / 3.; 3]);
+    }
+
+    #[test]
+    fn one_cpu_is_split_by_weight() {
+        let (heavy, light) = (nice_weight(0) as f64, nice_weight(5) as f64);
+        assert_close(&expected_shares(&[0, 5], &[1, 1], 1), &[heavy / (heavy + light), light / (heavy + light)]);
+    }
+
+    #[test]
+    fn a_thread_uses_one_cpu_at_most() {
+        // Each worker gets a CPU of its own, no matter the weights
+        assert_close(&expected_shares(&[0, 10], &[1, 1], 2), &[0.5, 0.5]);
+        // The heavy worker saturates its CPU, the two light ones split the other
+        assert_close(&expected_shares(&[-20, 19], &[1, 2], 2), &[0.5, 0.5]);
+    }
+
+    #[test]
+    fn jain_index_bounds() {
+        assert!((jain_index(&[2., 2., 2.]) - 1.).abs() < 1e-9);
+        assert!((jain_index(&[1., 0., 0.]) - 1. / 3.).abs() < 1e-9);
+        assert_eq!(jain_index(&[0., 0.]), 1.);
+    }
+
+    #[test]
+    fn max_min_ratio_of_shares() {
+        assert_eq!(max_min_ratio(&[2., 1., 4.]), 4.);
+        assert_eq!(max_min_ratio(&[1., 0.]), f64::INFINITY);
+        assert_eq!(max_min_ratio(&[0., 0.]), 1.);
+    }
+}
@@ -0,0 +1,352 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use std::thread;
+use std::time::Duration;
+
+use crate::aging::Aging;
+use crate::config::Config;
+use crate::interface;
+use crate::main_controller::{MainController, Timing, LATENCY_BUCKETS_US};
+use crate::workload::{self, WorkloadKind};
+use crate::{scheduler, share_model};
+use crate::{ProgramState, GROUP_AMOUNT};
+
+/// How often rates shown in the stats panel are recomputed, in seconds
+const SAMPLE_PERIOD: f32 = 0.5;
+/// Difference between two shares that gets flagged in the stats panel
+const DEVIATION_THRESHOLD: f64 = 0.1;
+
+const STATS_TEXT_COLOR: Color = Color::srgb(0.8, 0.8, 0.8);
+const STATS_WARNING_COLOR: Color = Color::srgb(0.95, 0.6, 0.3);
+const STATS_STARVING_COLOR: Color = Color::srgb(0.95, 0.3, 0.3);
+
+pub struct StatsPlugin;
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(StatsSampler::default())
+            .add_systems(OnEnter(ProgramState::Running), setup_stats_panel)
+            .add_systems(Update, update_stats_panel.run_if(in_state(ProgramState::Running)));
+    }
+}
+
+/// Node the per-group statistics are listed in
+#[derive(Component, Debug, Default, Clone)]
+pub struct StatsPanel;
+
+#[derive(Component, Debug, Default, Clone)]
+struct GroupSwatch(usize);
+
+#[derive(Component, Debug, Default, Clone)]
+struct GroupStatsText(usize);
+
+/// Fairness across all groups, above the group rows
+#[derive(Component, Debug, Default, Clone)]
+struct FairnessText;
+
+/// One bucket of a group's wake-up latency histogram, see [`LATENCY_BUCKETS_US`]
+#[derive(Component, Debug, Default, Clone)]
+struct HistogramBar {
+    group_index: usize,
+    bucket: usize,
+}
+
+/// Progress of every group at the last sample, used to compute rates
+#[derive(Resource, Default)]
+struct StatsSampler {
+    since_sample: f32,
+    last_progress: Vec<u64>,
+    rates: Vec<f64>,
+    last_cpu_time: Vec<Duration>,
+    /// Shares of the CPU time the workers used since the last sample
+    cpu_shares: Vec<f64>,
+    territory_shares: Vec<f64>,
+}
+
+impl StatsSampler {
+    fn sample(&mut self, main_controller: &MainController, elapsed: f32) {
+        let group_amount = main_controller.group_amount();
+        self.last_progress.resize(group_amount, 0);
+        self.rates.resize(group_amount, 0.);
+        self.last_cpu_time.resize(group_amount, Duration::ZERO);
+
+        let cpu_deltas: Vec<f64> = (0..group_amount).map(|group_index| {
+            let cpu_time = main_controller.cpu_time(group_index);
+            // CPU time goes backwards when a group gets respawned
+            let delta = cpu_time.saturating_sub(self.last_cpu_time[group_index]);
+            self.last_cpu_time[group_index] = cpu_time;
+            delta.as_secs_f64()
+        }).collect();
+        self.cpu_shares = share_model::shares(&cpu_deltas);
+        let territory: Vec<f64> = main_controller.territory().into_iter().map(|pixels| pixels as f64).collect();
+        self.territory_shares = share_model::shares(&territory);
+
+        for group_index in 0..group_amount {
+            let progress = main_controller.stats(group_index).progress();
+            // Progress goes backwards when a group gets respawned
+            let delta = progress.saturating_sub(self.last_progress[group_index]);
+            self.rates[group_index] = delta as f64 / elapsed as f64;
+            self.last_progress[group_index] = progress;
+        }
+    }
+}
+
+/// Shares the share model predicts for the priorities the groups actually run with,
+/// `None` if the scheduler doesn't share by weight or a group has no priority applied yet
+fn expected_shares(main_controller: &MainController) -> Option<Vec<f64>> {
+    let nice_values = (0..main_controller.group_amount())
+        .map(|group_index| main_controller.applied_priority(group_index).and_then(scheduler::nice_for_priority))
+        .collect::<Option<Vec<_>>>()?;
+    let worker_counts: Vec<usize> = (0..main_controller.group_amount()).map(|group_index| main_controller.worker_count(group_index)).collect();
+    let cpus = thread::available_parallelism().map(|cpus| cpus.get()).unwrap_or(1);
+    Some(share_model::expected_shares(&nice_values, &worker_counts, cpus))
+}
+
+fn setup_stats_panel(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    panels: Query<Entity, With<StatsPanel>>,
+) {
+    for panel in panels {
+        commands.entity(panel).with_children(|panel| {
+            panel.spawn((
+                FairnessText,
+                Text::new(""),
+                TextFont {
+                    font: asset_server.load(interface::FONT_PATH),
+                    font_size: 18.0,
+                    ..default()
+                },
+                TextColor(STATS_TEXT_COLOR),
+            ));
+            for group_index in 0..GROUP_AMOUNT as usize {
+                panel.spawn((
+                    Node {
+                        height: Val::Px(30.),
+                        margin: UiRect::vertical(Val::Px(3.)),
+                        flex_direction: FlexDirection::Row,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                )).with_children(|row| {
+                    row.spawn((
+                        GroupSwatch(group_index),
+                        Node {
+                            width: Val::Px(20.),
+                            height: Val::Px(20.),
+                            margin: UiRect::right(Val::Px(10.)),
+                            ..default()
+                        },
+                        BorderRadius::all(Val::Px(4.)),
+                        BackgroundColor(Color::BLACK),
+                    ));
+                    row.spawn((
+                        Node {
+                            width: Val::Px(10. * LATENCY_BUCKETS_US.len() as f32),
+                            height: Val::Px(24.),
+                            margin: UiRect::right(Val::Px(10.)),
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::FlexEnd,
+                            ..default()
+                        },
+                        BackgroundColor(Color::srgb(0.13, 0.13, 0.15)),
+                    )).with_children(|histogram| {
+                        for bucket in 0..LATENCY_BUCKETS_US.len() {
+                            histogram.spawn((
+                                HistogramBar { group_index, bucket },
+                                Node {
+                                    width: Val::Px(8.),
+                                    height: Val::Percent(0.),
+                                    margin: UiRect::horizontal(Val::Px(1.)),
+                                    ..default()
+                                },
+                                BackgroundColor(Color::srgb(0.7, 0.7, 0.7)),
+                            ));
+                        }
+                    });
+                    row.spawn((
+                        GroupStatsText(group_index),
+                        Text::new(""),
+                        TextFont {
+                            font: asset_server.load(interface::FONT_PATH),
+                            font_size: 18.0,
+                            ..default()
+                        },
+                        TextColor(STATS_TEXT_COLOR),
+                    ));
+                });
+            }
+        });
+    }
+}
+
+/// Everything on the stats panel that gets updated
+#[derive(SystemParam)]
+struct StatsWidgets<'w, 's> {
+    swatches: Query<'w, 's, (&'static mut BackgroundColor, &'static GroupSwatch)>,
+    texts: Query<'w, 's, (&'static mut Text, &'static mut TextColor, &'static GroupStatsText)>,
+    fairness_texts: Query<'w, 's, &'static mut Text, (With<FairnessText>, Without<GroupStatsText>)>,
+    histogram_bars: Query<'w, 's, (&'static mut Node, &'static HistogramBar)>,
+}
+
+fn update_stats_panel(
+    time: Res<Time>,
+    main_controller: Res<MainController>,
+    mut sampler: ResMut<StatsSampler>,
+    config: Res<Config>,
+    aging: Res<Aging>,
+    mut widgets: StatsWidgets,
+) {
+    sampler.since_sample += time.delta_secs();
+    if sampler.since_sample < SAMPLE_PERIOD { return }
+    let elapsed = std::mem::take(&mut sampler.since_sample);
+    sampler.sample(&main_controller, elapsed);
+
+    for (mut bg_color, swatch) in &mut widgets.swatches {
+        *bg_color = main_controller.color(swatch.0).into();
+    }
+    let expected_shares = expected_shares(&main_controller);
+    for (mut text, mut text_color, group_text) in &mut widgets.texts {
+        let group_index = group_text.0;
+        let workload = main_controller.workload(group_index);
+        text.0 = format!(
+            "G{}  {}  {} {}  ({}/s)",
+            group_index,
+            workload.name(),
+            format_si(main_controller.stats(group_index).progress() as f64),
+            workload.metric(),
+            format_si(sampler.rates[group_index]),
+        );
+
+        let boost = aging.applied_boost(group_index);
+        if boost > 0 {
+            text.0 += &format!("  boosted +{}", boost);
+        }
+
+        if let Some(limit) = main_controller.rate_limit(group_index) {
+            text.0 += &format!("  limit {}/s {}", format_si(limit.per_second), limit.unit.name());
+        }
+
+        let aborted = main_controller.stats(group_index).aborted();
+        if aborted > 0 {
+            text.0 += &format!("  {} aborted", format_si(aborted as f64));
+        }
+
+        // Only the timings the group's workload reports ever get recorded
+        let stats = main_controller.stats(group_index);
+        for timing in Timing::ALL {
+            let histogram = stats.timing(timing);
+            if histogram.count() == 0 { continue }
+            text.0 += &format!(
+                "  {}: mean {:.2}ms, p99 < {}, max {:.2}ms, total {:.1}s",
+                timing.name(),
+                histogram.mean().as_secs_f64() * 1000.,
+                format_bucket_bound(histogram.quantile_bound_us(0.99)),
+                histogram.max().as_secs_f64() * 1000.,
+                histogram.total().as_secs_f64(),
+            );
+        }
+
+        if workload == WorkloadKind::Pipeline {
+            if let Some(queue) = main_controller.input_queue(group_index) {
+                text.0 += &format!("  queue {}/{}", queue.len(), queue.capacity());
+            }
+            if main_controller.input_queue(group_index).is_some_and(|queue| !queue.has_producer()) {
+                text.0 += "  no producer";
+            }
+            if main_controller.output_queue(group_index).is_some_and(|queue| !queue.has_consumer()) {
+                text.0 += "  no consumer";
+            }
+        }
+
+        // A CPU share off the model points at the scheduler, a territory share off the CPU share at the workload
+        let cpu_share = sampler.cpu_shares[group_index];
+        let territory_share = sampler.territory_shares[group_index];
+        let mut deviates = false;
+        text.0 += &format!("  CPU {:.0}%", 100. * cpu_share);
+        if let Some(expected_share) = expected_shares.as_ref().map(|shares| shares[group_index]) {
+            text.0 += &format!(" / model {:.0}%", 100. * expected_share);
+            if (cpu_share - expected_share).abs() > DEVIATION_THRESHOLD {
+                text.0 += " [scheduler]";
+                deviates = true;
+            }
+        }
+        text.0 += &format!("  territory {:.0}%", 100. * territory_share);
+        if (territory_share - cpu_share).abs() > DEVIATION_THRESHOLD {
+            text.0 += " [workload]";
+            deviates = true;
+        }
+        text_color.0 = if deviates { STATS_WARNING_COLOR } else { STATS_TEXT_COLOR };
+
+        if main_controller.is_starving(group_index, config.starvation_threshold) {
+            text.0 += &format!(
+                "  STARVING, no progress for {:.1}s",
+                main_controller.stats(group_index).since_progress().as_secs_f64(),
+            );
+            text_color.0 = STATS_STARVING_COLOR;
+        }
+    }
+
+    // Throughput is only comparable when every group counts the same metric
+    let group_amount = main_controller.group_amount();
+    let same_metric = (1..group_amount).all(|group_index| main_controller.workload(group_index).metric() == main_controller.workload(0).metric());
+    let throughput_fairness = if same_metric { format!("{:.2}", share_model::jain_index(&sampler.rates)) } else { "-".to_string() };
+    let starving = (0..group_amount).filter(|group_index| main_controller.is_starving(*group_index, config.starvation_threshold)).count();
+    // Phases are shared by every group that runs them, so they're listed once
+    let phases = (0..group_amount).any(|group_index| main_controller.workload(group_index) == WorkloadKind::BarrierPhases)
+        .then(workload::phase_stats);
+    let rendering = (0..group_amount).any(|group_index| main_controller.workload(group_index) == WorkloadKind::TileRender);
+    for mut text in &mut widgets.fairness_texts {
+        text.0 = format!(
+            "Jain's index: CPU {:.2}, throughput {}  max/min CPU share {:.1}  starving groups {}",
+            share_model::jain_index(&sampler.cpu_shares),
+            throughput_fairness,
+            share_model::max_min_ratio(&sampler.cpu_shares),
+            starving,
+        );
+        if let Some(notice) = aging.notice() {
+            text.0 += &format!("  {notice}");
+        }
+        if let Some(tiles) = main_controller.tiles().filter(|_| rendering) {
+            text.0 += &format!("  tiles rendered {}/{}", tiles.rendered().min(tiles.total()), tiles.total());
+        }
+        if let Some((phases, last_phase, mean_phase)) = phases {
+            text.0 += &format!(
+                "  phases {}, last {:.1}ms, mean {:.1}ms",
+                phases,
+                last_phase.as_secs_f64() * 1000.,
+                mean_phase.as_secs_f64() * 1000.,
+            );
+        }
+    }
+    for (mut node, bar) in &mut widgets.histogram_bars {
+        let buckets = main_controller.stats(bar.group_index).timing(Timing::WakeupLatency).buckets();
+        let highest = buckets.iter().copied().max().unwrap_or(0).max(1);
+        node.height = Val::Percent(100. * buckets[bar.bucket] as f32 / highest as f32);
+    }
+}
+
+fn format_bucket_bound(bound_us: u64) -> String {
+    match bound_us {
+        u64::MAX => "inf".to_string(),
+        bound_us if bound_us >= 1000 => format!("{}ms", bound_us / 1000),
+        bound_us => format!("{}us", bound_us),
+    }
+}
+
+/// Shortens large numbers with a metric suffix, e.g. `12.3M`
+pub fn format_si(value: f64) -> String {
+    const SUFFIXES: [&str; 5] = ["", "k", "M", "G", "T"];
+    let mut value = value;
+    let mut suffix = 0;
+    while value.abs() >= 1000. && suffix < SUFFIXES.len() - 1 {
+        value /= 1000.;
+        suffix += 1;
+    }
+    if suffix == 0 {
+        format!("{:.0}", value)
+    } else {
+        format!("{:.1}{}", value, SUFFIXES[suffix])
+    }
+}
@@ -0,0 +1,133 @@
+use bevy::prelude::*;
+
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::main_controller::{MainController, MainImageData};
+use crate::{share_model, GROUP_AMOUNT};
+
+/// Batch experiment run instead of the app: every combination of group priorities in a range
+/// gets raced for a fixed time, without a window
+#[derive(Clone, Debug)]
+pub struct SweepSettings {
+    pub enabled: bool,
+    pub duration: Duration,
+    /// Lowest and highest priority tried, inclusive
+    pub range: (i32, i32),
+    /// CSV file the results table is written to
+    pub output: String,
+}
+
+impl Default for SweepSettings {
+    fn default() -> SweepSettings {
+        SweepSettings {
+            enabled: false,
+            duration: Duration::from_secs(5),
+            range: (-2, 2),
+            output: "sweep.csv".to_string(),
+        }
+    }
+}
+
+/// Territory and CPU shares of every group under one priority combination
+struct SweepRow {
+    priorities: Vec<i32>,
+    territory_shares: Vec<f64>,
+    cpu_shares: Vec<f64>,
+}
+
+/// Every assignment of priorities in `range` to `group_amount` groups
+fn combinations(range: (i32, i32), group_amount: usize) -> Vec<Vec<i32>> {
+    let (low, high) = range;
+    let mut combinations = vec![Vec::new()];
+    for _ in 0..group_amount {
+        combinations = combinations.into_iter()
+            .flat_map(|combination| (low..=high).map(move |priority| [combination.clone(), vec![priority]].concat()))
+            .collect();
+    }
+    combinations
+}
+
+/// Runs the whole sweep on the calling thread and writes the results table.
+/// Obstacle masks are asset files, so they don't apply here
+pub fn run(config: &Config) {
+    let settings = &config.sweep;
+    let main_image_data = MainImageData::new(Handle::default(), config.canvas_width as i32, config.canvas_height as i32);
+    main_image_data.canvas().set_boundary(config.boundary);
+    let mut main_controller = MainController::new(&main_image_data, config.group_settings(GROUP_AMOUNT));
+
+    let combinations = combinations(settings.range, GROUP_AMOUNT as usize);
+    println!(
+        "Sweeping {} priority combinations for {:.1}s each, about {:.0}s in total",
+        combinations.len(),
+        settings.duration.as_secs_f64(),
+        combinations.len() as f64 * settings.duration.as_secs_f64(),
+    );
+
+    let mut rows = Vec::new();
+    for (index, priorities) in combinations.into_iter().enumerate() {
+        // Every combination races on a fresh canvas with fresh workers
+        main_controller.reset(true);
+        if let Err(error) = main_controller.update_priorities(priorities.clone()) {
+            eprintln!("Couldn't apply priorities {priorities:?}: {error}");
+        }
+        main_controller.start_all().unwrap();
+        thread::sleep(settings.duration);
+        main_controller.stop_all().unwrap();
+
+        let territory: Vec<f64> = main_controller.territory().into_iter().map(|pixels| pixels as f64).collect();
+        let cpu_times: Vec<f64> = (0..main_controller.group_amount())
+            .map(|group_index| main_controller.cpu_time(group_index).as_secs_f64())
+            .collect();
+        let row = SweepRow { priorities, territory_shares: share_model::shares(&territory), cpu_shares: share_model::shares(&cpu_times) };
+        println!("[{}] {}", index + 1, format_row(&row, "  "));
+        rows.push(row);
+    }
+    main_controller.terminate();
+
+    let header = (0..GROUP_AMOUNT).map(|group| format!("priority_g{group}"))
+        .chain((0..GROUP_AMOUNT).map(|group| format!("territory_g{group}")))
+        .chain((0..GROUP_AMOUNT).map(|group| format!("cpu_g{group}")))
+        .collect::<Vec<_>>()
+        .join(",");
+    let table = std::iter::once(header)
+        .chain(rows.iter().map(|row| format_row(row, ",")))
+        .collect::<Vec<_>>()
+        .join("\n");
+    match fs::write(&settings.output, table + "\n") {
+        Ok(()) => println!("Results written to {}", settings.output),
+        Err(error) => eprintln!("Couldn't write {}: {error}", settings.output),
+    }
+}
+
+/// Priorities, then territory shares, then CPU shares
+fn format_row(row: &SweepRow, separator: &str) -> String {
+    row.priorities.iter().map(|priority| priority.to_string())
+        .chain(row.territory_shares.iter().map(|share| format!("{share:.4}")))
+        .chain(row.cpu_shares.iter().map(|share| format!("{share:.4}")))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_combination_once() {
+        let combinations = combinations((-1, 1), 2);
+        assert_eq!(combinations.len(), 9);
+        assert_eq!(combinations.first(), Some(&vec![-1, -1]));
+        assert_eq!(combinations.last(), Some(&vec![1, 1]));
+        for (index, combination) in combinations.iter().enumerate() {
+            assert!(!combinations[index + 1..].contains(combination));
+        }
+    }
+
+    #[test]
+    fn single_priority_range() {
+        assert_eq!(combinations((0, 0), 3), vec![vec![0, 0, 0]]);
+    }
+}
@@ -0,0 +1,174 @@
+use bevy::asset::LoadState;
+use bevy::prelude::*;
+use bevy::ui::RelativeCursorPosition;
+
+use crate::interface;
+use crate::main_controller::{MainController, MainImageData};
+use crate::{ProgramState, GROUP_AMOUNT};
+
+/// Lets the user act on the canvas with the mouse, depending on the selected tool
+pub struct ToolsPlugin;
+impl Plugin for ToolsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<ToolMode>()
+            .add_systems(Update, (tool_button_controller, canvas_tool_controller, apply_obstacle_mask)
+                .run_if(in_state(ProgramState::Running)));
+    }
+}
+
+/// What clicking on the canvas does
+#[derive(Resource, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ToolMode {
+    #[default]
+    None,
+    /// Plants the seed shape of the group with this index
+    PlaceSeed(usize),
+    /// Paints obstacles while the mouse is held down
+    PaintObstacle,
+    EraseObstacle,
+}
+
+/// Image from the `obstacle_mask` config key, applied to every new canvas
+#[derive(Resource)]
+pub struct ObstacleMask(pub Handle<Image>);
+
+/// Radius of the obstacle brush in canvas pixels
+const BRUSH_RADIUS: i32 = 3;
+
+impl ToolMode {
+    pub fn name(&self) -> String {
+        match self {
+            ToolMode::None => "No tool".to_string(),
+            ToolMode::PlaceSeed(group_index) => format!("Seed G{}", group_index),
+            ToolMode::PaintObstacle => "Obstacles".to_string(),
+            ToolMode::EraseObstacle => "Eraser".to_string(),
+        }
+    }
+
+    fn next(&self) -> ToolMode {
+        match *self {
+            ToolMode::None => ToolMode::PlaceSeed(0),
+            ToolMode::PlaceSeed(group_index) if group_index + 1 < GROUP_AMOUNT as usize => ToolMode::PlaceSeed(group_index + 1),
+            ToolMode::PlaceSeed(_) => ToolMode::PaintObstacle,
+            ToolMode::PaintObstacle => ToolMode::EraseObstacle,
+            ToolMode::EraseObstacle => ToolMode::None,
+        }
+    }
+}
+
+/// Canvas pixel under the cursor, `None` if the cursor isn't over the canvas
+pub fn canvas_pixel(cursor: &RelativeCursorPosition, main_image_data: &MainImageData) -> Option<[i32; 2]> {
+    if !cursor.mouse_over() { return None }
+    let normalized = cursor.normalized?;
+    Some([
+        ((normalized.x * main_image_data.width() as f32) as i32).clamp(0, main_image_data.width() - 1),
+        ((normalized.y * main_image_data.height() as f32) as i32).clamp(0, main_image_data.height() - 1)
+    ])
+}
+
+fn tool_button_controller(
+    mut tool_mode: ResMut<ToolMode>,
+    tool_button: Query<(&mut BackgroundColor, &Interaction), interface::ButtonInteraction<interface::ToolButton>>,
+    mut tool_labels: Query<&mut Text, With<interface::ToolLabel>>,
+) {
+    for (mut bg_color, interaction) in tool_button {
+        match *interaction {
+            Interaction::Pressed => {
+                *bg_color = interface::TOOL_BUTTON_PRESSED_COLOR.into();
+
+                *tool_mode = tool_mode.next();
+                tool_labels.iter_mut().for_each(|mut label| label.0 = tool_mode.name());
+            }
+            Interaction::Hovered => {
+                *bg_color = interface::TOOL_BUTTON_HOVERED.into();
+            }
+            Interaction::None => {
+                *bg_color = interface::TOOL_BUTTON_IDLE_COLOR.into();
+            }
+        };
+    }
+}
+
+/// Seeds are planted once per click, obstacles are painted for as long as the mouse is held down
+fn canvas_tool_controller(
+    tool_mode: Res<ToolMode>,
+    main_controller: Res<MainController>,
+    main_image_data: Res<MainImageData>,
+    canvas: Query<(&Interaction, &RelativeCursorPosition), With<interface::CanvasNode>>,
+    mut last_pos: Local<Option<[i32; 2]>>,
+) {
+    for (interaction, cursor) in canvas {
+        if *interaction != Interaction::Pressed {
+            *last_pos = None;
+            continue;
+        }
+        let Some(pos) = canvas_pixel(cursor, &main_image_data) else { continue };
+
+        match *tool_mode {
+            ToolMode::None => {}
+            ToolMode::PlaceSeed(group_index) => if last_pos.is_none() { main_controller.plant_seed(group_index, pos) },
+            ToolMode::PaintObstacle => paint_stroke(&main_image_data, last_pos.unwrap_or(pos), pos, true),
+            ToolMode::EraseObstacle => paint_stroke(&main_image_data, last_pos.unwrap_or(pos), pos, false),
+        }
+        *last_pos = Some(pos);
+    }
+}
+
+/// Stamps the brush along the line between two cursor positions, so fast strokes don't leave gaps
+fn paint_stroke(main_image_data: &MainImageData, from: [i32; 2], to: [i32; 2], obstacle: bool) {
+    let canvas = main_image_data.canvas();
+    let steps = (to[0] - from[0]).abs().max((to[1] - from[1]).abs()).max(1);
+
+    for step in 0..=steps {
+        let center = [
+            from[0] + (to[0] - from[0]) * step / steps,
+            from[1] + (to[1] - from[1]) * step / steps,
+        ];
+        for y in center[1] - BRUSH_RADIUS..=center[1] + BRUSH_RADIUS {
+            for x in center[0] - BRUSH_RADIUS..=center[0] + BRUSH_RADIUS {
+                if (x - center[0]).pow(2) + (y - center[1]).pow(2) > BRUSH_RADIUS.pow(2) { continue }
+                if x < 0 || y < 0 || x >= main_image_data.width() || y >= main_image_data.height() { continue }
+                canvas.set_obstacle(x as u32, y as u32, obstacle);
+            }
+        }
+    }
+}
+
+/// Turns the dark, opaque pixels of the mask into obstacles whenever a new canvas gets created
+fn apply_obstacle_mask(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mask: Option<Res<ObstacleMask>>,
+    images: Res<Assets<Image>>,
+    main_image_data: Res<MainImageData>,
+) {
+    let Some(mask) = mask else { return };
+    if !main_image_data.is_changed() { return }
+    let Some(mask_image) = images.get(&mask.0) else {
+        let path = mask.0.path().map_or_else(|| "?".to_string(), |path| path.to_string());
+        let error = match asset_server.load_state(&mask.0) {
+            LoadState::Failed(error) => error.to_string(),
+            state => format!("{state:?}"),
+        };
+        // Starts without obstacles
+        warn!("Couldn't load the obstacle mask {path}: {error}");
+        commands.remove_resource::<ObstacleMask>();
+        return;
+    };
+
+    let canvas = main_image_data.canvas();
+    let (mask_width, mask_height) = (mask_image.width(), mask_image.height());
+    for y in 0..canvas.height() {
+        for x in 0..canvas.width() {
+            // Nearest neighbor, the mask is stretched over the whole canvas
+            let mask_x = x * mask_width / canvas.width();
+            let mask_y = y * mask_height / canvas.height();
+            let Ok(color) = mask_image.get_color_at(mask_x, mask_y) else { continue };
+            let color = color.to_srgba();
+            if color.alpha > 0.5 && (color.red + color.green + color.blue) / 3. < 0.5 {
+                canvas.set_obstacle(x, y, true);
+            }
+        }
+    }
+}
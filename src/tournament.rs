@@ -0,0 +1,236 @@
+use bevy::prelude::*;
+
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::interface;
+use crate::main_controller::MainController;
+use crate::race::{self, EndConditions, GroupResult, OverlayLine};
+use crate::{PrioritiesContainer, ProgramState};
+
+/// Ends every run of a tournament when no end condition is configured
+const FALLBACK_TIME_LIMIT: Duration = Duration::from_secs(30);
+
+/// Two-sided 95% quantiles of Student's t distribution for 1 to 30 degrees of freedom
+const T_QUANTILES_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228,
+    2.201, 2.179, 2.160, 2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086,
+    2.080, 2.074, 2.069, 2.064, 2.060, 2.056, 2.052, 2.048, 2.045, 2.042,
+];
+const Z_95: f64 = 1.96;
+
+/// Repeats the race with the same priorities, resetting the canvas in between,
+/// and summarizes how often and by how much each group won
+pub struct TournamentPlugin;
+impl Plugin for TournamentPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Tournament>()
+            .add_systems(Update, (tournament_button_controller, advance_tournament).run_if(in_state(ProgramState::Running)));
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Tournament {
+    runs: u32,
+    results: Vec<Vec<GroupResult>>,
+    active: bool,
+    /// A run is under way, waiting for it to end
+    racing: bool,
+}
+
+impl Tournament {
+    pub fn is_active(&self) -> bool {self.active}
+
+    /// Runs need to end on their own, so a tournament without configured conditions gets a time limit
+    pub fn end_conditions(&self, config: &Config) -> EndConditions {
+        if self.active && config.end_conditions.is_empty() {
+            EndConditions { time_limit: Some(FALLBACK_TIME_LIMIT), ..default() }
+        } else {
+            config.end_conditions.clone()
+        }
+    }
+
+    /// Results of a finished run, ranked as by [`race::collect_results`]
+    pub fn record(&mut self, results: Vec<GroupResult>) {
+        self.results.push(results);
+        self.racing = false;
+    }
+
+    fn label(&self) -> String {
+        if self.active {
+            format!("Run {}/{}", (self.results.len() + 1).min(self.runs as usize), self.runs)
+        } else {
+            "Tournament".to_string()
+        }
+    }
+
+    fn summary(&self, main_controller: &MainController) -> Vec<OverlayLine> {
+        let runs = self.results.len();
+        let mut lines = vec![
+            (format!("Tournament of {} runs", runs), 32., race::OVERLAY_TITLE_COLOR),
+            ("win rates and territory shares with 95% confidence intervals".to_string(), 20., race::OVERLAY_NOTE_COLOR),
+        ];
+
+        for group_index in 0..main_controller.group_amount() {
+            // Runs where nobody painted anything have no winner
+            let wins = self.results.iter()
+                .filter(|run| run.first().is_some_and(|winner| winner.group_index == group_index && winner.pixels > 0))
+                .count();
+            let shares: Vec<f64> = self.results.iter().map(|run| {
+                let total: u64 = run.iter().map(|result| result.pixels).sum();
+                let pixels = run.iter().find(|result| result.group_index == group_index).map_or(0, |result| result.pixels);
+                if total == 0 { 0. } else { pixels as f64 / total as f64 }
+            }).collect();
+            let priority = self.results.first()
+                .and_then(|run| run.iter().find(|result| result.group_index == group_index))
+                .and_then(|result| result.priority);
+
+            let (win_low, win_high) = wilson_interval(wins, runs);
+            let (share_mean, share_variance) = mean_variance(&shares);
+            let share_margin = t_quantile(runs) * (share_variance / runs.max(1) as f64).sqrt();
+            lines.push((
+                format!(
+                    "G{} at priority {}  wins {}/{} = {:.0}% [{:.0}%, {:.0}%]  share {:.1}% ± {:.1}% (variance {:.4})",
+                    group_index,
+                    priority.map_or("-".to_string(), |priority| priority.to_string()),
+                    wins,
+                    runs,
+                    100. * wins as f64 / runs.max(1) as f64,
+                    100. * win_low,
+                    100. * win_high,
+                    100. * share_mean,
+                    100. * share_margin,
+                    share_variance,
+                ),
+                24.,
+                main_controller.color(group_index).into(),
+            ));
+        }
+        lines
+    }
+}
+
+/// Sample mean and unbiased sample variance
+pub fn mean_variance(samples: &[f64]) -> (f64, f64) {
+    if samples.is_empty() { return (0., 0.) }
+    let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+    if samples.len() < 2 { return (mean, 0.) }
+    let variance = samples.iter().map(|sample| (sample - mean).powi(2)).sum::<f64>() / (samples.len() - 1) as f64;
+    (mean, variance)
+}
+
+/// Quantile for a 95% interval of the mean of `samples` samples
+pub fn t_quantile(samples: usize) -> f64 {
+    match samples.saturating_sub(1) {
+        0 => f64::INFINITY,
+        degrees_of_freedom if degrees_of_freedom <= T_QUANTILES_95.len() => T_QUANTILES_95[degrees_of_freedom - 1],
+        _ => Z_95,
+    }
+}
+
+/// 95% Wilson score interval of a proportion, well behaved for small samples and rates near 0 or 1
+fn wilson_interval(successes: usize, trials: usize) -> (f64, f64) {
+    if trials == 0 { return (0., 1.) }
+    let (n, p) = (trials as f64, successes as f64 / trials as f64);
+    let denominator = 1. + Z_95 * Z_95 / n;
+    let center = (p + Z_95 * Z_95 / (2. * n)) / denominator;
+    let margin = Z_95 * (p * (1. - p) / n + Z_95 * Z_95 / (4. * n * n)).sqrt() / denominator;
+    ((center - margin).max(0.), (center + margin).min(1.))
+}
+
+fn tournament_button_controller(
+    main_controller: Res<MainController>,
+    config: Res<Config>,
+    mut tournament: ResMut<Tournament>,
+    tournament_button: Query<(&mut BackgroundColor, &Interaction), interface::ButtonInteraction<interface::TournamentButton>>,
+    mut tournament_labels: Query<&mut Text, With<interface::TournamentLabel>>,
+) {
+    for (mut bg_color, interaction) in tournament_button {
+        match *interaction {
+            Interaction::Pressed => {
+                *bg_color = interface::TOOL_BUTTON_PRESSED_COLOR.into();
+
+                if tournament.active {
+                    main_controller.stop_all().unwrap();
+                    *tournament = Tournament::default();
+                } else {
+                    *tournament = Tournament { runs: config.tournament_runs, active: true, ..default() };
+                }
+                tournament_labels.iter_mut().for_each(|mut label| label.0 = tournament.label());
+            }
+            Interaction::Hovered => {
+                *bg_color = interface::TOOL_BUTTON_HOVERED.into();
+            }
+            Interaction::None => {
+                *bg_color = interface::TOOL_BUTTON_IDLE_COLOR.into();
+            }
+        };
+    }
+}
+
+/// Starts the next run once the previous one ended, or shows the summary after the last one
+fn advance_tournament(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut main_controller: ResMut<MainController>,
+    priorities_container: Res<PrioritiesContainer>,
+    mut tournament: ResMut<Tournament>,
+    mut tournament_labels: Query<&mut Text, With<interface::TournamentLabel>>,
+) {
+    if !tournament.active || tournament.racing { return }
+
+    if tournament.results.len() >= tournament.runs as usize {
+        race::spawn_overlay(&mut commands, tournament.summary(&main_controller), asset_server.load(interface::FONT_PATH));
+        tournament.active = false;
+    } else {
+        // Colors are kept, so groups stay recognizable from run to run
+        main_controller.reset(true);
+        if let Err(error) = main_controller.update_priorities(priorities_container.priorities.clone()) {
+            error!("Couldn't update priorities: {error}");
+        }
+        main_controller.start_all().unwrap();
+        tournament.racing = true;
+    }
+    tournament_labels.iter_mut().for_each(|mut label| label.0 = tournament.label());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wilson_interval_without_trials_is_everything() {
+        assert_eq!(wilson_interval(0, 0), (0., 1.));
+    }
+
+    #[test]
+    fn wilson_interval_matches_known_values() {
+        let (low, high) = wilson_interval(5, 10);
+        assert!((low - 0.2366).abs() < 1e-3 && (high - 0.7634).abs() < 1e-3, "{low}..{high}");
+        let (low, high) = wilson_interval(0, 10);
+        assert_eq!(low, 0.);
+        assert!((high - 0.2775).abs() < 1e-3, "{high}");
+        let (low, high) = wilson_interval(10, 10);
+        assert!((low - 0.7225).abs() < 1e-3, "{low}");
+        assert_eq!(high, 1.);
+    }
+
+    #[test]
+    fn t_quantile_falls_back_to_the_normal_distribution() {
+        assert_eq!(t_quantile(0), f64::INFINITY);
+        assert_eq!(t_quantile(1), f64::INFINITY);
+        assert_eq!(t_quantile(2), 12.706);
+        assert_eq!(t_quantile(31), 2.042);
+        assert_eq!(t_quantile(32), Z_95);
+    }
+
+    #[test]
+    fn mean_variance_uses_the_sample_variance() {
+        assert_eq!(mean_variance(&[]), (0., 0.));
+        assert_eq!(mean_variance(&[3.]), (3., 0.));
+        let (mean, variance) = mean_variance(&[1., 2., 3., 4.]);
+        assert_eq!(mean, 2.5);
+        assert!((variance - 5. / 3.).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,260 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::Duration;
+
+use crate::main_controller::{Color, GroupStats, MainImageData, RateLimit, BACKGROUND_COLOR};
+
+mod barrier;
+mod dla;
+mod interactive;
+mod kernels;
+mod lock;
+mod memory;
+mod pipeline;
+pub mod seed;
+mod tiles;
+mod war;
+
+pub use barrier::{pause_phases, phase_stats, reset_phases, resume_phases, BarrierPhases};
+pub use dla::{Dla, DlaParams, LaunchMode, Neighborhood};
+pub use interactive::Interactive;
+pub use kernels::{Mandelbrot, MonteCarloPi, PrimeSieve};
+pub use lock::{lock_kind, set_lock_kind, LockHolder, LockWaiter};
+pub use memory::{FalseSharing, PointerChase, StreamBuffer};
+pub use pipeline::{Pipeline, PipelineStage, StageQueue};
+pub use seed::{SeedPosition, SeedSettings, SeedShape};
+pub use tiles::{Fractal, TileQueue, TileRender, TileSettings};
+pub use war::TerritoryWar;
+
+/// Something a worker thread repeats while its group is running.
+/// Every worker owns its own instance, so implementations don't need any synchronization
+pub trait Workload: Send {
+    /// Prepares the canvas before the group's workers start. Called once per group
+    fn init(&mut self, _painter: &Painter) {}
+    /// Performs one unit of work and returns the progress it made, counted in the kind's metric.
+    /// Workers check whether they should pause after every batch of units, so a unit should stay short
+    fn work(&mut self, painter: &Painter) -> u64;
+    /// Draws the results of the last unit of work onto the canvas
+    fn draw(&mut self, painter: &Painter);
+    /// How many units the worker performs between checking whether it should pause
+    fn batch_size(&self) -> u32 { 1000 }
+    /// Records what the last unit of work measured, like how long it waited, into the group's stats
+    fn report(&mut self, _stats: &GroupStats) {}
+}
+
+/// Parameters workloads are created with
+#[derive(Clone, Debug)]
+pub struct WorkloadSettings {
+    /// How long the interactive workload sleeps between bursts
+    pub interactive_period: Duration,
+    pub dla: DlaParams,
+    pub seed: SeedSettings,
+    /// Token bucket limit the group starts with, set per group like the workload parameters
+    pub rate_limit: Option<RateLimit>,
+    /// Queues to the neighboring groups, set up by the main controller
+    pub pipeline: PipelineStage,
+    pub tiles: TileSettings,
+    /// Runs the group's workers on a single CPU, the same one for every pinned group
+    pub pinned: bool,
+}
+
+impl Default for WorkloadSettings {
+    fn default() -> WorkloadSettings {
+        WorkloadSettings {
+            interactive_period: Duration::from_millis(5),
+            dla: DlaParams::default(),
+            seed: SeedSettings::default(),
+            rate_limit: None,
+            pipeline: PipelineStage::default(),
+            tiles: TileSettings::default(),
+            pinned: false,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum WorkloadKind {
+    #[default]
+    Dla,
+    Mandelbrot,
+    MonteCarloPi,
+    PrimeSieve,
+    StreamBuffer,
+    PointerChase,
+    FalseSharing,
+    Interactive,
+    LockHolder,
+    LockWaiter,
+    Pipeline,
+    BarrierPhases,
+    TerritoryWar,
+    TileRender,
+}
+
+impl WorkloadKind {
+    pub const ALL: [WorkloadKind; 14] = [
+        WorkloadKind::Dla,
+        WorkloadKind::Mandelbrot,
+        WorkloadKind::MonteCarloPi,
+        WorkloadKind::PrimeSieve,
+        WorkloadKind::StreamBuffer,
+        WorkloadKind::PointerChase,
+        WorkloadKind::FalseSharing,
+        WorkloadKind::Interactive,
+        WorkloadKind::LockHolder,
+        WorkloadKind::LockWaiter,
+        WorkloadKind::Pipeline,
+        WorkloadKind::BarrierPhases,
+        WorkloadKind::TerritoryWar,
+        WorkloadKind::TileRender,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WorkloadKind::Dla => "DLA",
+            WorkloadKind::Mandelbrot => "Mandelbrot",
+            WorkloadKind::MonteCarloPi => "Monte-Carlo pi",
+            WorkloadKind::PrimeSieve => "Prime sieve",
+            WorkloadKind::StreamBuffer => "Stream",
+            WorkloadKind::PointerChase => "Pointer chase",
+            WorkloadKind::FalseSharing => "False sharing",
+            WorkloadKind::Interactive => "Interactive",
+            WorkloadKind::LockHolder => "Lock holder",
+            WorkloadKind::LockWaiter => "Lock waiter",
+            WorkloadKind::Pipeline => "Pipeline stage",
+            WorkloadKind::BarrierPhases => "Barrier phases",
+            WorkloadKind::TerritoryWar => "Territory war",
+            WorkloadKind::TileRender => "Tile render",
+        }
+    }
+
+    /// What the progress returned by [`Workload::work`] counts
+    pub fn metric(&self) -> &'static str {
+        match self {
+            WorkloadKind::Dla => "attachments",
+            WorkloadKind::Mandelbrot => "tiles",
+            WorkloadKind::MonteCarloPi => "samples",
+            WorkloadKind::PrimeSieve => "primes",
+            WorkloadKind::StreamBuffer => "bytes",
+            WorkloadKind::PointerChase => "hops",
+            WorkloadKind::FalseSharing => "increments",
+            WorkloadKind::Interactive => "wake-ups",
+            WorkloadKind::LockHolder | WorkloadKind::LockWaiter => "critical sections",
+            WorkloadKind::Pipeline => "items",
+            WorkloadKind::BarrierPhases => "phases",
+            WorkloadKind::TerritoryWar => "conquests",
+            WorkloadKind::TileRender => "tiles",
+        }
+    }
+
+    pub fn next(&self) -> WorkloadKind {
+        let index = WorkloadKind::ALL.iter().position(|kind| kind == self).unwrap_or(0);
+        WorkloadKind::ALL[(index + 1) % WorkloadKind::ALL.len()]
+    }
+
+    pub fn create(&self, settings: &WorkloadSettings) -> Box<dyn Workload> {
+        match self {
+            WorkloadKind::Dla => Box::new(Dla::new(settings.dla.clone(), settings.seed.clone())),
+            WorkloadKind::Mandelbrot => Box::new(Mandelbrot::new()),
+            WorkloadKind::MonteCarloPi => Box::new(MonteCarloPi::new()),
+            WorkloadKind::PrimeSieve => Box::new(PrimeSieve::new()),
+            WorkloadKind::StreamBuffer => Box::new(StreamBuffer::new()),
+            WorkloadKind::PointerChase => Box::new(PointerChase::new()),
+            WorkloadKind::FalseSharing => Box::new(FalseSharing::new()),
+            WorkloadKind::Interactive => Box::new(Interactive::new(settings.interactive_period)),
+            WorkloadKind::LockHolder => Box::new(LockHolder::new()),
+            WorkloadKind::LockWaiter => Box::new(LockWaiter::new()),
+            WorkloadKind::Pipeline => Box::new(Pipeline::new(settings.pipeline.clone())),
+            WorkloadKind::BarrierPhases => Box::new(BarrierPhases::new()),
+            WorkloadKind::TerritoryWar => Box::new(TerritoryWar::new(settings.seed.clone())),
+            WorkloadKind::TileRender => Box::new(TileRender::new(settings.tiles.clone())),
+        }
+    }
+}
+
+const BACKGROUND: Color = Color(BACKGROUND_COLOR[0], BACKGROUND_COLOR[1], BACKGROUND_COLOR[2], BACKGROUND_COLOR[3]);
+
+/// Bounding box of the pixels a group's cluster is made of
+pub struct ClusterBounds {
+    min: [AtomicI32; 2],
+    max: [AtomicI32; 2],
+}
+
+impl ClusterBounds {
+    fn new() -> ClusterBounds {
+        ClusterBounds {
+            min: [AtomicI32::new(i32::MAX), AtomicI32::new(i32::MAX)],
+            max: [AtomicI32::new(i32::MIN), AtomicI32::new(i32::MIN)],
+        }
+    }
+    pub fn extend(&self, pos: [i32; 2]) {
+        for ((min, max), coordinate) in self.min.iter().zip(&self.max).zip(pos) {
+            min.fetch_min(coordinate, Ordering::Relaxed);
+            max.fetch_max(coordinate, Ordering::Relaxed);
+        }
+    }
+    /// Lowest and highest corner, `None` while the cluster is empty
+    pub fn get(&self) -> Option<([i32; 2], [i32; 2])> {
+        let min = [self.min[0].load(Ordering::Relaxed), self.min[1].load(Ordering::Relaxed)];
+        let max = [self.max[0].load(Ordering::Relaxed), self.max[1].load(Ordering::Relaxed)];
+        if min[0] > max[0] || min[1] > max[1] { return None }
+        Some((min, max))
+    }
+}
+
+/// A group's access to the shared canvas
+#[derive(Clone)]
+pub struct Painter {
+    image_data: Arc<MainImageData>,
+    color: Color,
+    bounds: Arc<ClusterBounds>,
+}
+
+impl Painter {
+    pub fn new(image_data: Arc<MainImageData>, color: Color) -> Painter {
+        Painter { image_data, color, bounds: Arc::new(ClusterBounds::new()) }
+    }
+    pub fn color(&self) -> Color {self.color}
+    /// Where the group's cluster has grown so far, kept up to date by the workloads that grow one
+    pub fn bounds(&self) -> &ClusterBounds {&self.bounds}
+    pub fn width(&self) -> i32 {self.image_data.width()}
+    pub fn height(&self) -> i32 {self.image_data.height()}
+
+    pub fn random_pos(&self) -> [i32; 2] {
+        [
+            rand::random_range(0..self.width()),
+            rand::random_range(0..self.height())
+        ]
+    }
+
+    /// Maps a position onto the canvas according to the boundary mode.
+    /// `None` if the position fell off an absorbing wall
+    pub fn resolve(&self, pos: [i32; 2]) -> Option<[i32; 2]> {
+        let boundary = self.image_data.canvas().boundary();
+        Some([
+            boundary.resolve(pos[0], self.width())?,
+            boundary.resolve(pos[1], self.height())?
+        ])
+    }
+
+    /// Past an absorbing wall there is only background
+    pub fn get_color(&self, x: i32, y: i32) -> Color {
+        let Some(pos) = self.resolve([x, y]) else { return BACKGROUND };
+        let [r, g, b, a] = self.image_data.canvas().pixel(pos[0] as u32, pos[1] as u32);
+        Color(r, g, b, a)
+    }
+
+    pub fn is_obstacle(&self, pos: [i32; 2]) -> bool {
+        let Some(pos) = self.resolve(pos) else { return false };
+        self.image_data.canvas().is_obstacle(pos[0] as u32, pos[1] as u32)
+    }
+
+    /// Drawing past an absorbing wall or over an obstacle does nothing
+    pub fn set_color(&self, x: i32, y: i32, color: Color) {
+        let Some(pos) = self.resolve([x, y]) else { return };
+        let canvas = self.image_data.canvas();
+        if canvas.is_obstacle(pos[0] as u32, pos[1] as u32) { return }
+        canvas.set_pixel(pos[0] as u32, pos[1] as u32, [color.0, color.1, color.2, color.3]);
+        canvas.dirty_tiles().mark(pos[0] as u32, pos[1] as u32);
+    }
+}
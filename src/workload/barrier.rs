@@ -0,0 +1,178 @@
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::main_controller::{GroupStats, Timing};
+
+use super::kernels::{draw_lateness, spin};
+use super::{Painter, Workload};
+
+/// Work every worker does in a phase, the same for all groups
+const PHASE_ITERATIONS: u32 = 2_000_000;
+/// Longest a worker waits at the barrier at once, so it still reaches its checkpoints
+const BARRIER_TIMEOUT: Duration = Duration::from_millis(10);
+/// Far longer than a phase takes when every group gets the same CPU time
+const BARRIER_WAIT_SCALE: Duration = Duration::from_millis(50);
+
+struct BarrierState {
+    /// Workers running the phase workload, across all groups
+    participants: usize,
+    arrived: usize,
+    /// Phases completed so far
+    generation: u64,
+    /// When the current phase started, or was resumed after all groups stopped
+    phase_started: Option<Instant>,
+    /// Time the current phase ran before all groups stopped
+    phase_elapsed: Duration,
+    /// Whether all groups are stopped, workers finishing their batch don't restart the clock
+    paused: bool,
+    last_phase: Duration,
+    total_phases: Duration,
+}
+
+impl BarrierState {
+    /// Starts the next phase once everybody arrived
+    fn release_if_complete(&mut self, released: &Condvar) {
+        if self.participants == 0 || self.arrived < self.participants { return }
+        let now = Instant::now();
+        self.last_phase = self.phase_elapsed + self.phase_started.map_or(Duration::ZERO, |started| now.duration_since(started));
+        self.total_phases += self.last_phase;
+        self.phase_elapsed = Duration::ZERO;
+        self.phase_started = (!self.paused).then_some(now);
+        self.arrived = 0;
+        self.generation += 1;
+        released.notify_all();
+    }
+}
+
+/// Barrier every phase worker of every group meets at. Workers join when they're created
+/// and leave when they're dropped, so respawning a group doesn't leave the others waiting forever
+struct PhaseBarrier {
+    state: Mutex<BarrierState>,
+    released: Condvar,
+}
+
+static PHASE_BARRIER: PhaseBarrier = PhaseBarrier {
+    state: Mutex::new(BarrierState {
+        participants: 0,
+        arrived: 0,
+        generation: 0,
+        phase_started: None,
+        phase_elapsed: Duration::ZERO,
+        paused: false,
+        last_phase: Duration::ZERO,
+        total_phases: Duration::ZERO,
+    }),
+    released: Condvar::new(),
+};
+
+/// Stops the phase clock while no group runs
+pub fn pause_phases() {
+    let mut state = PHASE_BARRIER.state.lock().unwrap();
+    state.paused = true;
+    if let Some(phase_started) = state.phase_started.take() {
+        state.phase_elapsed += phase_started.elapsed();
+    }
+}
+
+/// Lets the next worker to run restart the phase clock
+pub fn resume_phases() {
+    PHASE_BARRIER.state.lock().unwrap().paused = false;
+}
+
+/// Forgets the phases completed so far. Meant for when no phase worker is left, the workers that are keep their place
+pub fn reset_phases() {
+    let mut state = PHASE_BARRIER.state.lock().unwrap();
+    state.generation = 0;
+    state.phase_started = None;
+    state.phase_elapsed = Duration::ZERO;
+    state.last_phase = Duration::ZERO;
+    state.total_phases = Duration::ZERO;
+}
+
+/// Completed phases, and the duration of the last one and of all of them on average
+pub fn phase_stats() -> (u64, Duration, Duration) {
+    let state = PHASE_BARRIER.state.lock().unwrap();
+    let mean = if state.generation > 0 { state.total_phases / state.generation as u32 } else { Duration::ZERO };
+    (state.generation, state.last_phase, mean)
+}
+
+/// Works through phases of equal work, meeting every other phase worker at a barrier after each.
+/// A stopped or starved group holds everybody back, no matter their priority
+pub struct BarrierPhases {
+    /// Phase the worker arrived at the barrier in, and when
+    arrival: Option<(u64, Instant)>,
+    last_wait: Option<Duration>,
+    state: u64,
+}
+
+impl BarrierPhases {
+    pub fn new() -> BarrierPhases {
+        let mut barrier = PHASE_BARRIER.state.lock().unwrap();
+        barrier.participants += 1;
+        BarrierPhases { arrival: None, last_wait: None, state: 1 }
+    }
+}
+
+impl Workload for BarrierPhases {
+    /// Either the work of one phase and the arrival at the barrier, or one more wait for the others
+    fn work(&mut self, _painter: &Painter) -> u64 {
+        let (generation, arrived) = match self.arrival {
+            Some(arrival) => arrival,
+            None => {
+                // The first phase starts with the first worker that gets to run, not when the workers are created
+                let mut barrier = PHASE_BARRIER.state.lock().unwrap();
+                if !barrier.paused {
+                    barrier.phase_started.get_or_insert_with(Instant::now);
+                }
+                drop(barrier);
+                self.state = spin(PHASE_ITERATIONS, self.state);
+                let mut barrier = PHASE_BARRIER.state.lock().unwrap();
+                let arrival = (barrier.generation, Instant::now());
+                barrier.arrived += 1;
+                barrier.release_if_complete(&PHASE_BARRIER.released);
+                self.arrival = Some(arrival);
+                arrival
+            }
+        };
+
+        let barrier = PHASE_BARRIER.state.lock().unwrap();
+        let (barrier, _) = PHASE_BARRIER.released
+            .wait_timeout_while(barrier, BARRIER_TIMEOUT, |barrier| barrier.generation == generation)
+            .unwrap();
+        if barrier.generation == generation { return 0 }
+
+        self.arrival = None;
+        self.last_wait = Some(arrived.elapsed());
+        1
+    }
+
+    /// Marks a random pixel, the longer the wait at the barrier the darker it is
+    fn draw(&mut self, painter: &Painter) {
+        let Some(waited) = self.last_wait else { return };
+        draw_lateness(painter, waited, BARRIER_WAIT_SCALE);
+    }
+
+    fn batch_size(&self) -> u32 { 10 }
+
+    fn report(&mut self, stats: &GroupStats) {
+        if let Some(waited) = self.last_wait.take() {
+            stats.record_timing(Timing::BarrierWait, waited);
+        }
+    }
+}
+
+impl Drop for BarrierPhases {
+    fn drop(&mut self) {
+        let mut barrier = PHASE_BARRIER.state.lock().unwrap();
+        barrier.participants -= 1;
+        if barrier.participants == 0 {
+            barrier.phase_started = None;
+            barrier.phase_elapsed = Duration::ZERO;
+        }
+        if self.arrival.is_some_and(|(generation, _)| generation == barrier.generation) {
+            barrier.arrived -= 1;
+        }
+        // The others may have been waiting for just this worker
+        barrier.release_if_complete(&PHASE_BARRIER.released);
+    }
+}
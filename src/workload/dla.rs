@@ -0,0 +1,196 @@
+use std::f64::consts::TAU;
+
+use crate::main_controller::GroupStats;
+
+use super::seed::{self, SeedSettings};
+use super::{Painter, Workload};
+
+pub(super) const FOUR_NEIGHBORS: [(i32, i32); 4] = [(1,0), (-1,0), (0,1), (0,-1)];
+const EIGHT_NEIGHBORS: [(i32, i32); 8] = [(1,0), (-1,0), (0,1), (0,-1), (1,1), (1,-1), (-1,1), (-1,-1)];
+
+/// Distance between the cluster and the ring walkers are launched from
+const RING_MARGIN: f64 = 5.;
+/// Walkers launched from the ring give up once they are this many ring radii away from the cluster
+const RING_KILL_FACTOR: f64 = 3.;
+/// Points of the launch ring tried before settling for the canvas point closest to one
+const RING_LAUNCH_ATTEMPTS: u32 = 16;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Neighborhood {
+    #[default]
+    Four,
+    Eight,
+}
+
+/// Where walkers start from
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LaunchMode {
+    /// Anywhere on the canvas
+    #[default]
+    Uniform,
+    /// On a circle just outside the cluster
+    Ring,
+    /// On the border of the canvas
+    Edge,
+}
+
+#[derive(Clone, Debug)]
+pub struct DlaParams {
+    /// Chance that a walker touching the cluster sticks to it instead of walking on
+    pub sticking_probability: f64,
+    pub neighborhood: Neighborhood,
+    pub launch: LaunchMode,
+    /// Steps a walker gets before it gives up
+    pub step_budget: u32,
+}
+
+impl Default for DlaParams {
+    fn default() -> DlaParams {
+        DlaParams {
+            sticking_probability: 1.,
+            neighborhood: Neighborhood::default(),
+            launch: LaunchMode::default(),
+            step_budget: 1000,
+        }
+    }
+}
+
+/// Diffusion-limited aggregation: walkers wander randomly until they touch the group's cluster and stick to it
+pub struct Dla {
+    params: DlaParams,
+    seed: SeedSettings,
+    attached: Option<[i32; 2]>,
+    aborted: bool,
+}
+
+impl Dla {
+    pub fn new(params: DlaParams, seed: SeedSettings) -> Dla {
+        Dla { params, seed, attached: None, aborted: false }
+    }
+
+    fn move_random_direction(&self, pos: &mut [i32; 2]) {
+        let direction = rand::random_range(0..4);
+        let (x_dir, y_dir) = match direction {
+            0 => ( 1,  0),
+            1 => (-1,  0),
+            2 => ( 0,  1),
+            3 => ( 0, -1),
+            _ => panic!("Random range for a step direction has been setup wrongfully")
+        }; 
+        (pos[0], pos[1]) = (pos[0] + x_dir, pos[1] + y_dir);
+    }
+
+    /// Center and radius of the launch ring, `None` while the cluster is empty
+    fn launch_ring(&self, painter: &Painter) -> Option<([f64; 2], f64)> {
+        let (min, max) = painter.bounds().get()?;
+        let center = [(min[0] + max[0]) as f64 / 2., (min[1] + max[1]) as f64 / 2.];
+        let half_diagonal = ((max[0] - min[0]) as f64).hypot((max[1] - min[1]) as f64) / 2.;
+        Some((center, half_diagonal + RING_MARGIN))
+    }
+
+    fn launch_pos(&self, painter: &Painter) -> [i32; 2] {
+        match self.params.launch {
+            LaunchMode::Uniform => painter.random_pos(),
+            LaunchMode::Ring => match self.launch_ring(painter) {
+                Some((center, radius)) => {
+                    let ring_pos = || {
+                        let angle = rand::random::<f64>() * TAU;
+                        [
+                            (center[0] + radius * angle.cos()) as i32,
+                            (center[1] + radius * angle.sin()) as i32
+                        ]
+                    };
+                    // Past an absorbing wall the ring goes on off the canvas, launching there isn't a walk
+                    (0..RING_LAUNCH_ATTEMPTS).map(|_| ring_pos()).find(|pos| painter.resolve(*pos).is_some())
+                        .unwrap_or_else(|| {
+                            let pos = ring_pos();
+                            [pos[0].clamp(0, painter.width() - 1), pos[1].clamp(0, painter.height() - 1)]
+                        })
+                }
+                None => painter.random_pos(),
+            },
+            LaunchMode::Edge => {
+                let pos = painter.random_pos();
+                match rand::random_range(0..4) {
+                    0 => [0, pos[1]],
+                    1 => [painter.width() - 1, pos[1]],
+                    2 => [pos[0], 0],
+                    _ => [pos[0], painter.height() - 1],
+                }
+            }
+        }
+    }
+
+    fn is_near_neighbor(&self, painter: &Painter, pos: &[i32; 2]) -> bool {
+        let neighbors: &[(i32, i32)] = match self.params.neighborhood {
+            Neighborhood::Four => &FOUR_NEIGHBORS,
+            Neighborhood::Eight => &EIGHT_NEIGHBORS,
+        };
+        for (x_bias, y_bias) in neighbors {
+
+            let neighboring_color = painter.get_color(pos[0] + x_bias, pos[1] + y_bias);
+            if neighboring_color == painter.color() {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+impl Workload for Dla {
+    fn init(&mut self, painter: &Painter) {
+        seed::plant_seeds(painter, &self.seed);
+    }
+
+    /// One walk, from the launch position until the walker sticks or runs out of steps
+    fn work(&mut self, painter: &Painter) -> u64 {
+        // Launch positions are on the canvas, only walkers that walk off it count as aborted
+        let Some(mut pos) = painter.resolve(self.launch_pos(painter)) else { return 0 };
+        // Walkers launched inside a wall are simply dropped
+        if painter.is_obstacle(pos) { return 0 }
+        let kill_distance = match self.params.launch {
+            LaunchMode::Ring => self.launch_ring(painter).map(|(center, radius)| (center, radius * RING_KILL_FACTOR)),
+            _ => None,
+        };
+
+        for _ in 0..self.params.step_budget {
+            let previous = pos;
+            self.move_random_direction(&mut pos);
+            pos = match painter.resolve(pos) {
+                Some(pos) => pos,
+                None => {
+                    self.aborted = true;
+                    return 0;
+                }
+            };
+            // Walkers that didn't stick can't walk into the cluster, nor can anyone walk through obstacles
+            if painter.get_color(pos[0], pos[1]) == painter.color() || painter.is_obstacle(pos) {
+                pos = previous;
+                continue;
+            }
+
+            if self.is_near_neighbor(painter, &pos)
+                && rand::random::<f64>() < self.params.sticking_probability {
+                self.attached = Some(pos);
+                return 1;
+            }
+
+            if let Some((center, distance)) = kill_distance
+                && (pos[0] as f64 - center[0]).hypot(pos[1] as f64 - center[1]) > distance { return 0 }
+        }
+        0
+    }
+
+    fn draw(&mut self, painter: &Painter) {
+        if let Some(pos) = self.attached.take() {
+            painter.set_color(pos[0], pos[1], painter.color());
+            painter.bounds().extend(pos);
+        }
+    }
+
+    fn report(&mut self, stats: &GroupStats) {
+        if std::mem::take(&mut self.aborted) {
+            stats.count_aborted();
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::main_controller::{GroupStats, Timing};
+
+use super::kernels::{draw_lateness, spin};
+use super::{Painter, Workload};
+
+const BURST_ITERATIONS: u32 = 20_000;
+/// An interactive thread waking up this late already feels sluggish
+const LATENESS_SCALE: Duration = Duration::from_millis(5);
+
+/// Behaves like an interactive thread: sleeps, wakes up, does a short burst of work.
+/// Measures how late every wake-up was, the way cyclictest does
+pub struct Interactive {
+    period: Duration,
+    last_latency: Option<Duration>,
+}
+
+impl Interactive {
+    pub fn new(period: Duration) -> Interactive {
+        Interactive { period, last_latency: None }
+    }
+}
+
+impl Workload for Interactive {
+    /// One sleep and the burst that follows it
+    fn work(&mut self, _painter: &Painter) -> u64 {
+        let wake_up = Instant::now() + self.period;
+        thread::sleep(self.period);
+        self.last_latency = Some(Instant::now().saturating_duration_since(wake_up));
+
+        spin(BURST_ITERATIONS, 1);
+        1
+    }
+
+    /// Marks a random pixel, the later the wake-up the darker it is
+    fn draw(&mut self, painter: &Painter) {
+        let Some(latency) = self.last_latency else { return };
+        draw_lateness(painter, latency, LATENESS_SCALE);
+    }
+
+    /// About 100ms of sleeping between checkpoints
+    fn batch_size(&self) -> u32 {
+        (100 / self.period.as_millis().max(1)).max(1) as u32
+    }
+
+    fn report(&mut self, stats: &GroupStats) {
+        if let Some(latency) = self.last_latency.take() {
+            stats.record_timing(Timing::WakeupLatency, latency);
+        }
+    }
+}
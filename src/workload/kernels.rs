@@ -0,0 +1,212 @@
+use std::hint::black_box;
+use std::time::Duration;
+
+use crate::main_controller::Color;
+
+use super::{Painter, Workload};
+
+/// Scales a color towards black, `brightness` is in `0.0..=1.0`
+pub(super) fn shade(color: Color, brightness: f32) -> Color {
+    Color(
+        (color.0 as f32 * brightness) as u8,
+        (color.1 as f32 * brightness) as u8,
+        (color.2 as f32 * brightness) as u8,
+        color.3
+    )
+}
+
+/// Busy work the compiler can't skip. Returns the state to pass into the next call
+pub(super) fn spin(iterations: u32, state: u64) -> u64 {
+    let mut state = state;
+    for _ in 0..iterations {
+        state = black_box(state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407));
+    }
+    state
+}
+
+/// The group's color, darkened the further `value` gets towards `scale`
+pub(super) fn lateness_shade(painter: &Painter, value: Duration, scale: Duration) -> Color {
+    let lateness = (value.as_secs_f32() / scale.as_secs_f32()).min(1.);
+    shade(painter.color(), 1. - 0.8 * lateness)
+}
+
+/// Marks a random pixel in the group's color, darkened the further `value` gets towards `scale`
+pub(super) fn draw_lateness(painter: &Painter, value: Duration, scale: Duration) {
+    let pos = painter.random_pos();
+    painter.set_color(pos[0], pos[1], lateness_shade(painter, value, scale));
+}
+
+const MANDELBROT_TILE_SIZE: i32 = 8;
+const MANDELBROT_MAX_ITERATIONS: u32 = 64;
+
+/// Renders random tiles of the Mandelbrot set, shaded in the group's color
+pub struct Mandelbrot {
+    tile_pos: [i32; 2],
+    iterations: [u32; (MANDELBROT_TILE_SIZE * MANDELBROT_TILE_SIZE) as usize],
+}
+
+impl Mandelbrot {
+    pub fn new() -> Mandelbrot {
+        Mandelbrot {
+            tile_pos: [0, 0],
+            iterations: [0; (MANDELBROT_TILE_SIZE * MANDELBROT_TILE_SIZE) as usize],
+        }
+    }
+
+    fn escape_time(c_re: f64, c_im: f64) -> u32 {
+        let (mut z_re, mut z_im) = (0., 0.);
+        for iteration in 0..MANDELBROT_MAX_ITERATIONS {
+            if z_re * z_re + z_im * z_im > 4. {
+                return iteration;
+            }
+            (z_re, z_im) = (z_re * z_re - z_im * z_im + c_re, 2. * z_re * z_im + c_im);
+        }
+        MANDELBROT_MAX_ITERATIONS
+    }
+}
+
+impl Workload for Mandelbrot {
+    /// One tile
+    fn work(&mut self, painter: &Painter) -> u64 {
+        let pos = painter.random_pos();
+        self.tile_pos = [
+            pos[0] - pos[0] % MANDELBROT_TILE_SIZE,
+            pos[1] - pos[1] % MANDELBROT_TILE_SIZE
+        ];
+
+        let (width, height) = (painter.width() as f64, painter.height() as f64);
+        for index in 0..self.iterations.len() {
+            let x = self.tile_pos[0] + index as i32 % MANDELBROT_TILE_SIZE;
+            let y = self.tile_pos[1] + index as i32 / MANDELBROT_TILE_SIZE;
+            let c_re = -2.2 + 3.2 * x as f64 / width;
+            let c_im = -1.2 + 2.4 * y as f64 / height;
+            self.iterations[index] = Mandelbrot::escape_time(c_re, c_im);
+        }
+        1
+    }
+
+    fn draw(&mut self, painter: &Painter) {
+        for (index, iterations) in self.iterations.iter().enumerate() {
+            let x = self.tile_pos[0] + index as i32 % MANDELBROT_TILE_SIZE;
+            let y = self.tile_pos[1] + index as i32 / MANDELBROT_TILE_SIZE;
+            if x >= painter.width() || y >= painter.height() { continue }
+
+            let brightness = *iterations as f32 / MANDELBROT_MAX_ITERATIONS as f32;
+            painter.set_color(x, y, shade(painter.color(), brightness));
+        }
+    }
+}
+
+const MONTE_CARLO_SAMPLES: u32 = 1000;
+
+/// Estimates pi by throwing random points into a unit square and counting the ones inside the quarter circle
+pub struct MonteCarloPi {
+    inside: u64,
+    total: u64,
+    last_sample: (f64, f64),
+}
+
+impl MonteCarloPi {
+    pub fn new() -> MonteCarloPi {
+        MonteCarloPi { inside: 0, total: 0, last_sample: (0., 0.) }
+    }
+}
+
+impl Workload for MonteCarloPi {
+    /// A fixed batch of samples
+    fn work(&mut self, _painter: &Painter) -> u64 {
+        for _ in 0..MONTE_CARLO_SAMPLES {
+            let (x, y): (f64, f64) = (rand::random(), rand::random());
+            if x * x + y * y <= 1. {
+                self.inside += 1;
+            }
+            self.last_sample = (x, y);
+        }
+        self.total += MONTE_CARLO_SAMPLES as u64;
+        MONTE_CARLO_SAMPLES as u64
+    }
+
+    /// Plots the last sample of the batch, points inside the circle are drawn brighter
+    fn draw(&mut self, painter: &Painter) {
+        let (x, y) = self.last_sample;
+        let brightness = if x * x + y * y <= 1. { 1. } else { 0.35 };
+        let pixel_x = (x * painter.width() as f64) as i32;
+        let pixel_y = ((1. - y) * painter.height() as f64) as i32;
+        painter.set_color(pixel_x, pixel_y, shade(painter.color(), brightness));
+    }
+}
+
+const SIEVE_SEGMENT_SIZE: u64 = 1024;
+
+/// Segmented sieve of Eratosthenes. Every found prime lights up the pixel at `prime % pixel_count`
+pub struct PrimeSieve {
+    base_primes: Vec<u64>,
+    base_limit: u64,
+    segment_start: u64,
+    segment: Vec<bool>,
+    found: Vec<u64>,
+}
+
+impl PrimeSieve {
+    pub fn new() -> PrimeSieve {
+        PrimeSieve {
+            base_primes: Vec::new(),
+            base_limit: 1,
+            // Workers of a group start at different places so they don't paint the same pixels
+            segment_start: rand::random_range(0..1_000_000) * SIEVE_SEGMENT_SIZE,
+            segment: vec![true; SIEVE_SEGMENT_SIZE as usize],
+            found: Vec::new(),
+        }
+    }
+
+    /// Makes sure every prime up to `limit` is known
+    fn extend_base_primes(&mut self, limit: u64) {
+        if limit <= self.base_limit { return }
+        let new_limit = limit * 2;
+
+        let mut is_prime = vec![true; new_limit as usize + 1];
+        is_prime[0] = false;
+        is_prime[1] = false;
+        let mut number = 2;
+        while number * number <= new_limit {
+            if is_prime[number as usize] {
+                (number * number..=new_limit).step_by(number as usize).for_each(|multiple| is_prime[multiple as usize] = false);
+            }
+            number += 1;
+        }
+
+        self.base_primes = (2..=new_limit).filter(|number| is_prime[*number as usize]).collect();
+        self.base_limit = new_limit;
+    }
+}
+
+impl Workload for PrimeSieve {
+    /// One segment of the number line
+    fn work(&mut self, _painter: &Painter) -> u64 {
+        let low = self.segment_start;
+        let high = low + SIEVE_SEGMENT_SIZE;
+        self.extend_base_primes((high as f64).sqrt() as u64 + 1);
+
+        self.segment.fill(true);
+        for prime in &self.base_primes {
+            if prime * prime >= high { break }
+            let first_multiple = (prime * prime).max(low.div_ceil(*prime) * prime);
+            (first_multiple..high).step_by(*prime as usize).for_each(|multiple| self.segment[(multiple - low) as usize] = false);
+        }
+
+        self.found.clear();
+        self.found.extend(
+            (low.max(2)..high).filter(|number| self.segment[(number - low) as usize])
+        );
+        self.segment_start = high;
+        self.found.len() as u64
+    }
+
+    fn draw(&mut self, painter: &Painter) {
+        let pixel_count = (painter.width() * painter.height()) as u64;
+        for prime in &self.found {
+            let index = (prime % pixel_count) as i32;
+            painter.set_color(index % painter.width(), index / painter.width(), painter.color());
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::main_controller::{GroupStats, Timing};
+use crate::pi_lock::PiMutex;
+
+use super::kernels::{lateness_shade, spin};
+use super::{Painter, Workload};
+
+/// Work done inside a critical section is counted in iterations rather than time,
+/// so a holder that gets preempted keeps everyone else waiting for longer
+const HOLDER_CRITICAL_ITERATIONS: u32 = 5_000_000;
+const HOLDER_OUTSIDE_ITERATIONS: u32 = 500_000;
+const WAITER_CRITICAL_ITERATIONS: u32 = 20_000;
+const WAITER_PAUSE: Duration = Duration::from_millis(1);
+/// Roughly one of the holder's critical sections, a waiter that waited this long got stuck behind one
+const LOCK_WAIT_SCALE: Duration = Duration::from_millis(20);
+const MARK_SIZE: i32 = 3;
+
+/// The resource every lock workload shares, across all groups. It's guarded by one of two locks,
+/// switching between them shows the inversion and its fix on the same workers
+static SHARED_RESOURCE: Mutex<u64> = Mutex::new(0);
+static PI_SHARED_RESOURCE: PiMutex<u64> = PiMutex::new(0);
+static USE_PI_LOCK: AtomicBool = AtomicBool::new(false);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LockKind {
+    Std,
+    PriorityInheritance,
+}
+
+impl LockKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LockKind::Std => "std Mutex",
+            LockKind::PriorityInheritance => "PI lock",
+        }
+    }
+
+    pub fn next(&self) -> LockKind {
+        match self {
+            LockKind::Std => LockKind::PriorityInheritance,
+            LockKind::PriorityInheritance => LockKind::Std,
+        }
+    }
+}
+
+/// Lock the lock workloads take the shared resource with
+pub fn lock_kind() -> LockKind {
+    if USE_PI_LOCK.load(Ordering::Relaxed) { LockKind::PriorityInheritance } else { LockKind::Std }
+}
+
+/// Takes effect at the next critical section
+pub fn set_lock_kind(kind: LockKind) {
+    USE_PI_LOCK.store(kind == LockKind::PriorityInheritance, Ordering::Relaxed);
+}
+
+/// Waits for the shared resource, then works on it for `iterations`. Returns how long the wait took
+fn critical_section(iterations: u32) -> Duration {
+    let wait_start = Instant::now();
+    match lock_kind() {
+        LockKind::Std => {
+            // A worker that panicked inside doesn't make the resource unusable for the demonstration
+            let mut resource = SHARED_RESOURCE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let waited = wait_start.elapsed();
+            *resource = spin(iterations, *resource);
+            waited
+        }
+        LockKind::PriorityInheritance => {
+            let mut resource = PI_SHARED_RESOURCE.lock();
+            let waited = wait_start.elapsed();
+            *resource = spin(iterations, *resource);
+            waited
+        }
+    }
+}
+
+/// Marks a random spot, bigger than a pixel since critical sections are rare.
+/// The longer the wait for the lock the darker it is
+fn draw_wait(painter: &Painter, waited: Duration) {
+    let color = lateness_shade(painter, waited, LOCK_WAIT_SCALE);
+    let pos = painter.random_pos();
+    for dy in 0..MARK_SIZE {
+        for dx in 0..MARK_SIZE {
+            painter.set_color(pos[0] + dx, pos[1] + dy, color);
+        }
+    }
+}
+
+/// Takes the shared lock for long critical sections. Meant for a low priority group
+pub struct LockHolder {
+    last_wait: Option<Duration>,
+    state: u64,
+}
+
+impl LockHolder {
+    pub fn new() -> LockHolder {
+        LockHolder { last_wait: None, state: 1 }
+    }
+}
+
+impl Workload for LockHolder {
+    /// One critical section and some work outside of it
+    fn work(&mut self, _painter: &Painter) -> u64 {
+        self.last_wait = Some(critical_section(HOLDER_CRITICAL_ITERATIONS));
+        self.state = spin(HOLDER_OUTSIDE_ITERATIONS, self.state);
+        1
+    }
+
+    fn draw(&mut self, painter: &Painter) {
+        let Some(waited) = self.last_wait else { return };
+        draw_wait(painter, waited);
+    }
+
+    fn batch_size(&self) -> u32 { 10 }
+
+    fn report(&mut self, stats: &GroupStats) {
+        if let Some(waited) = self.last_wait.take() {
+            stats.record_timing(Timing::LockWait, waited);
+        }
+    }
+}
+
+/// Needs the shared lock often, for short critical sections. Meant for a high priority group
+pub struct LockWaiter {
+    last_wait: Option<Duration>,
+}
+
+impl LockWaiter {
+    pub fn new() -> LockWaiter {
+        LockWaiter { last_wait: None }
+    }
+}
+
+impl Workload for LockWaiter {
+    /// A short pause, then one critical section
+    fn work(&mut self, _painter: &Painter) -> u64 {
+        thread::sleep(WAITER_PAUSE);
+        self.last_wait = Some(critical_section(WAITER_CRITICAL_ITERATIONS));
+        1
+    }
+
+    fn draw(&mut self, painter: &Painter) {
+        let Some(waited) = self.last_wait else { return };
+        draw_wait(painter, waited);
+    }
+
+    fn batch_size(&self) -> u32 { 50 }
+
+    fn report(&mut self, stats: &GroupStats) {
+        if let Some(waited) = self.last_wait.take() {
+            stats.record_timing(Timing::LockWait, waited);
+        }
+    }
+}
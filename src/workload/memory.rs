@@ -0,0 +1,161 @@
+use std::hint::black_box;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::{Painter, Workload};
+
+/// Marks a random pixel, the memory workloads have nothing of their own to draw
+fn draw_random_pixel(painter: &Painter) {
+    let pos = painter.random_pos();
+    painter.set_color(pos[0], pos[1], painter.color());
+}
+
+const STREAM_BUFFER_SIZE: usize = 32 * 1024 * 1024;
+const STREAM_CHUNK_SIZE: usize = 256 * 1024;
+const WORD_SIZE: usize = size_of::<u64>();
+
+/// Bandwidth bound: sweeps a buffer much larger than the caches, reading and writing every word
+pub struct StreamBuffer {
+    buffer: Vec<u64>,
+    offset: usize,
+}
+
+impl StreamBuffer {
+    pub fn new() -> StreamBuffer {
+        StreamBuffer { buffer: Vec::new(), offset: 0 }
+    }
+}
+
+impl Workload for StreamBuffer {
+    /// One chunk of the buffer
+    fn work(&mut self, _painter: &Painter) -> u64 {
+        // Allocated on the worker thread, so respawning a group doesn't stall the UI
+        if self.buffer.is_empty() {
+            self.buffer = vec![1; STREAM_BUFFER_SIZE / WORD_SIZE];
+        }
+
+        let chunk_words = STREAM_CHUNK_SIZE / WORD_SIZE;
+        let chunk = &mut self.buffer[self.offset..self.offset + chunk_words];
+        for word in chunk.iter_mut() {
+            *word = word.wrapping_mul(3).wrapping_add(1);
+        }
+        black_box(&chunk);
+
+        self.offset = (self.offset + chunk_words) % self.buffer.len();
+        STREAM_CHUNK_SIZE as u64
+    }
+
+    fn draw(&mut self, painter: &Painter) {
+        draw_random_pixel(painter);
+    }
+}
+
+const POINTER_CHASE_NODES: usize = 8 * 1024 * 1024;
+const POINTER_CHASE_HOPS: u32 = 1000;
+
+/// Latency bound: follows a random linked list that spans far more memory than the caches,
+/// every hop depends on the previous load
+pub struct PointerChase {
+    next: Vec<u32>,
+    current: u32,
+}
+
+impl PointerChase {
+    pub fn new() -> PointerChase {
+        PointerChase { next: Vec::new(), current: 0 }
+    }
+
+    /// Links all nodes into a single random cycle with Sattolo's algorithm
+    fn build_list(&mut self) {
+        let mut order: Vec<u32> = (0..POINTER_CHASE_NODES as u32).collect();
+        for index in (1..order.len()).rev() {
+            let other = rand::random_range(0..index);
+            order.swap(index, other);
+        }
+
+        self.next = vec![0; POINTER_CHASE_NODES];
+        for window in order.windows(2) {
+            self.next[window[0] as usize] = window[1];
+        }
+        self.next[order[order.len() - 1] as usize] = order[0];
+    }
+}
+
+impl Workload for PointerChase {
+    /// A fixed number of hops
+    fn work(&mut self, _painter: &Painter) -> u64 {
+        // Built on the worker thread, so respawning a group doesn't stall the UI
+        if self.next.is_empty() {
+            self.build_list();
+        }
+
+        let mut current = self.current;
+        for _ in 0..POINTER_CHASE_HOPS {
+            current = self.next[current as usize];
+        }
+        self.current = black_box(current);
+        POINTER_CHASE_HOPS as u64
+    }
+
+    fn draw(&mut self, painter: &Painter) {
+        draw_random_pixel(painter);
+    }
+}
+
+const FALSE_SHARING_INCREMENTS: u32 = 10_000;
+const COUNTERS_PER_LINE: usize = 8;
+/// Enough for the four workers of each of the three groups. Once a line is full the next one fills up,
+/// workers beyond the last counter share it
+const SHARED_LINE_AMOUNT: usize = 2;
+const COUNTER_AMOUNT: usize = SHARED_LINE_AMOUNT * COUNTERS_PER_LINE;
+
+/// Counters packed into a single cache line
+#[repr(align(64))]
+struct SharedLine([AtomicU64; COUNTERS_PER_LINE]);
+
+/// Shared by every false sharing worker of every group
+static SHARED_LINES: [SharedLine; SHARED_LINE_AMOUNT] =
+    [const { SharedLine([const { AtomicU64::new(0) }; COUNTERS_PER_LINE]) }; SHARED_LINE_AMOUNT];
+/// Counters handed out to live workers, one bit each
+static TAKEN_COUNTERS: Mutex<u32> = Mutex::new(0);
+
+/// Coherence bound: every worker increments only its own counter, but the counters are packed into cache lines,
+/// so the lines keep bouncing between cores
+pub struct FalseSharing {
+    counter: usize,
+}
+
+impl FalseSharing {
+    /// Takes the first free counter, workers of respawned groups give theirs back
+    pub fn new() -> FalseSharing {
+        let mut taken = TAKEN_COUNTERS.lock().unwrap();
+        let counter = (taken.trailing_ones() as usize).min(COUNTER_AMOUNT - 1);
+        *taken |= 1 << counter;
+        FalseSharing { counter }
+    }
+
+    fn counter(&self) -> &AtomicU64 {
+        &SHARED_LINES[self.counter / COUNTERS_PER_LINE].0[self.counter % COUNTERS_PER_LINE]
+    }
+}
+
+impl Drop for FalseSharing {
+    fn drop(&mut self) {
+        *TAKEN_COUNTERS.lock().unwrap() &= !(1 << self.counter);
+    }
+}
+
+impl Workload for FalseSharing {
+    /// A fixed number of increments
+    fn work(&mut self, _painter: &Painter) -> u64 {
+        let counter = self.counter();
+        for _ in 0..FALSE_SHARING_INCREMENTS {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+        FALSE_SHARING_INCREMENTS as u64
+    }
+
+    fn draw(&mut self, painter: &Painter) {
+        draw_random_pixel(painter);
+    }
+}
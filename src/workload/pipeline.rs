@@ -0,0 +1,187 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::main_controller::{GroupStats, Timing};
+
+use super::kernels::{draw_lateness, spin};
+use super::{Painter, Workload};
+
+/// Work every stage does on an item
+const STAGE_ITERATIONS: u32 = 50_000;
+/// Longest a stage blocks on its queues at once, so its workers still reach their checkpoints
+const QUEUE_TIMEOUT: Duration = Duration::from_millis(10);
+/// An item this old spent most of its life waiting in queues
+const AGE_SCALE: Duration = Duration::from_millis(100);
+
+/// Something passed down the pipeline, stamped when the first stage produced it
+#[derive(Debug)]
+pub struct WorkItem {
+    created: Instant,
+    value: u64,
+}
+
+/// Bounded queue between two neighboring pipeline stages
+#[derive(Debug)]
+pub struct StageQueue {
+    items: Mutex<VecDeque<WorkItem>>,
+    capacity: usize,
+    not_empty: Condvar,
+    not_full: Condvar,
+    /// Pipeline workers pushing into and popping from the queue. A group running another workload leaves its end open
+    producers: AtomicUsize,
+    consumers: AtomicUsize,
+}
+
+impl StageQueue {
+    pub fn new(capacity: usize) -> StageQueue {
+        StageQueue {
+            items: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity: capacity.max(1),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            producers: AtomicUsize::new(0),
+            consumers: AtomicUsize::new(0),
+        }
+    }
+    pub fn len(&self) -> usize {self.items.lock().unwrap().len()}
+    pub fn capacity(&self) -> usize {self.capacity}
+    pub fn has_producer(&self) -> bool {self.producers.load(Ordering::Relaxed) > 0}
+    pub fn has_consumer(&self) -> bool {self.consumers.load(Ordering::Relaxed) > 0}
+    /// Drops the items of an earlier race or of a stage that left the pipeline
+    pub fn clear(&self) {
+        self.items.lock().unwrap().clear();
+        self.not_full.notify_all();
+    }
+
+    /// Waits up to `timeout` for room. Gives the item back if there wasn't any
+    fn push(&self, item: WorkItem, timeout: Duration) -> Result<(), WorkItem> {
+        let items = self.items.lock().unwrap();
+        let (mut items, _) = self.not_full.wait_timeout_while(items, timeout, |items| items.len() >= self.capacity).unwrap();
+        if items.len() >= self.capacity { return Err(item) }
+        items.push_back(item);
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Waits up to `timeout` for an item
+    fn pop(&self, timeout: Duration) -> Option<WorkItem> {
+        let items = self.items.lock().unwrap();
+        let (mut items, _) = self.not_empty.wait_timeout_while(items, timeout, |items| items.is_empty()).unwrap();
+        let item = items.pop_front()?;
+        self.not_full.notify_one();
+        Some(item)
+    }
+}
+
+/// Where a group sits in the pipeline. The first group has no input and produces the items,
+/// the last one has no output and retires them
+#[derive(Clone, Debug)]
+pub struct PipelineStage {
+    /// Items the queue after this stage holds at most
+    pub capacity: usize,
+    pub input: Option<Arc<StageQueue>>,
+    pub output: Option<Arc<StageQueue>>,
+}
+
+impl Default for PipelineStage {
+    fn default() -> PipelineStage {
+        PipelineStage { capacity: 64, input: None, output: None }
+    }
+}
+
+/// Takes items from the previous group's queue, works on them and hands them to the next group's queue
+pub struct Pipeline {
+    stage: PipelineStage,
+    /// Item that didn't fit into the output queue yet
+    pending: Option<WorkItem>,
+    last_age: Option<Duration>,
+    blocked: Duration,
+    retired_latency: Option<Duration>,
+}
+
+impl Pipeline {
+    pub fn new(stage: PipelineStage) -> Pipeline {
+        if let Some(input) = &stage.input {
+            input.consumers.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(output) = &stage.output {
+            output.producers.fetch_add(1, Ordering::Relaxed);
+        }
+        Pipeline { stage, pending: None, last_age: None, blocked: Duration::ZERO, retired_latency: None }
+    }
+
+    /// Blocks on the queue and counts the time towards the stage's blocking time,
+    /// unless nobody is at the other end of the queue
+    fn blocking<T>(&mut self, connected: bool, operation: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = operation();
+        if connected {
+            self.blocked += start.elapsed();
+        }
+        result
+    }
+}
+
+impl Workload for Pipeline {
+    /// Passes on the item the output queue had no room for, or processes a new one
+    fn work(&mut self, _painter: &Painter) -> u64 {
+        self.last_age = None;
+        let item = match self.pending.take() {
+            Some(item) => item,
+            None => {
+                let item = match self.stage.input.clone() {
+                    Some(input) => match self.blocking(input.has_producer(), || input.pop(QUEUE_TIMEOUT)) {
+                        Some(item) => item,
+                        None => return 0,
+                    },
+                    None => WorkItem { created: Instant::now(), value: 1 },
+                };
+                WorkItem { value: spin(STAGE_ITERATIONS, item.value), ..item }
+            }
+        };
+
+        let created = item.created;
+        match self.stage.output.clone() {
+            Some(output) => {
+                if let Err(item) = self.blocking(output.has_consumer(), || output.push(item, QUEUE_TIMEOUT)) {
+                    self.pending = Some(item);
+                    return 0;
+                }
+            }
+            None => self.retired_latency = Some(created.elapsed()),
+        }
+        self.last_age = Some(created.elapsed());
+        1
+    }
+
+    /// Marks a random pixel, the older the item the darker it is
+    fn draw(&mut self, painter: &Painter) {
+        let Some(age) = self.last_age else { return };
+        draw_lateness(painter, age, AGE_SCALE);
+    }
+
+    /// A blocked unit gives up after [`QUEUE_TIMEOUT`], so checkpoints stay 100ms apart at most
+    fn batch_size(&self) -> u32 { 10 }
+
+    fn report(&mut self, stats: &GroupStats) {
+        if !self.blocked.is_zero() {
+            stats.record_timing(Timing::QueueWait, std::mem::take(&mut self.blocked));
+        }
+        if let Some(latency) = self.retired_latency.take() {
+            stats.record_timing(Timing::ItemLatency, latency);
+        }
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        if let Some(input) = &self.stage.input {
+            input.consumers.fetch_sub(1, Ordering::Relaxed);
+        }
+        if let Some(output) = &self.stage.output {
+            output.producers.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::f64::consts::TAU;
+
+use super::Painter;
+
+/// What a group's cluster starts from
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum SeedShape {
+    /// A single pixel
+    #[default]
+    Point,
+    /// Separate pixels spread evenly on a circle around the seed position
+    Points(u32),
+    /// A horizontal line of the given length
+    Line(u32),
+    /// A circle outline of the given radius
+    Circle(u32),
+}
+
+/// Where the seed shape is centered
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum SeedPosition {
+    #[default]
+    Random,
+    /// Groups are spaced evenly around the canvas center. Resolved into [`SeedPosition::At`]
+    /// once the group's index is known, treated as the canvas center until then
+    Symmetric,
+    /// Fraction of the canvas width and height
+    At([f32; 2]),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SeedSettings {
+    pub shape: SeedShape,
+    pub position: SeedPosition,
+}
+
+impl SeedPosition {
+    /// The slot of a group when all groups are spaced evenly around the canvas center
+    pub fn symmetric(group_index: usize, group_amount: usize) -> SeedPosition {
+        let angle = TAU * group_index as f64 / group_amount.max(1) as f64;
+        SeedPosition::At([
+            (0.5 + 0.3 * angle.cos()) as f32,
+            (0.5 + 0.3 * angle.sin()) as f32
+        ])
+    }
+}
+
+/// Plants the group's seed shape at its configured position
+pub fn plant_seeds(painter: &Painter, settings: &SeedSettings) {
+    let center = match settings.position {
+        SeedPosition::Random => painter.random_pos(),
+        SeedPosition::Symmetric => [painter.width() / 2, painter.height() / 2],
+        SeedPosition::At(fraction) => [
+            (fraction[0] * painter.width() as f32) as i32,
+            (fraction[1] * painter.height() as f32) as i32
+        ],
+    };
+    plant(painter, settings.shape, center);
+}
+
+/// Plants a seed shape centered on the given pixel
+pub fn plant(painter: &Painter, shape: SeedShape, center: [i32; 2]) {
+    let plant_pixel = |pos: [i32; 2]| {
+        let Some(pos) = painter.resolve(pos) else { return };
+        if painter.is_obstacle(pos) { return }
+        painter.set_color(pos[0], pos[1], painter.color());
+        painter.bounds().extend(pos);
+    };
+
+    match shape {
+        SeedShape::Point => plant_pixel(center),
+        SeedShape::Points(amount) => {
+            let radius = painter.width().min(painter.height()) as f64 / 6.;
+            for index in 0..amount {
+                let angle = TAU * index as f64 / amount as f64;
+                plant_pixel([
+                    center[0] + (radius * angle.cos()) as i32,
+                    center[1] + (radius * angle.sin()) as i32
+                ]);
+            }
+        }
+        SeedShape::Line(length) => {
+            let start = center[0] - length as i32 / 2;
+            (start..start + length as i32).for_each(|x| plant_pixel([x, center[1]]));
+        }
+        SeedShape::Circle(radius) => {
+            // Enough steps to leave no gaps in the outline
+            let steps = (TAU * radius as f64).ceil().max(1.) as u32 * 2;
+            for step in 0..steps {
+                let angle = TAU * step as f64 / steps as f64;
+                plant_pixel([
+                    center[0] + (radius as f64 * angle.cos()).round() as i32,
+                    center[1] + (radius as f64 * angle.sin()).round() as i32
+                ]);
+            }
+        }
+    }
+}
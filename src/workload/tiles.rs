@@ -0,0 +1,138 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::main_controller::Color;
+
+use super::{Painter, Workload};
+
+const TILE_SIZE: i32 = 32;
+const MAX_ITERATIONS: u32 = 256;
+/// Parameter of the Julia set, one with plenty of detail
+const JULIA_C: (f64, f64) = (-0.8, 0.156);
+/// How long a worker rests once every tile is taken, instead of spinning on the empty queue
+const IDLE_PAUSE: Duration = Duration::from_millis(10);
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Fractal {
+    #[default]
+    Mandelbrot,
+    Julia,
+}
+
+/// Tiles of the canvas every group takes work from, in rows from the top left
+#[derive(Debug)]
+pub struct TileQueue {
+    columns: i32,
+    rows: i32,
+    next: AtomicUsize,
+    rendered: AtomicUsize,
+}
+
+impl TileQueue {
+    pub fn new(width: i32, height: i32) -> TileQueue {
+        TileQueue {
+            columns: (width + TILE_SIZE - 1) / TILE_SIZE,
+            rows: (height + TILE_SIZE - 1) / TILE_SIZE,
+            next: AtomicUsize::new(0),
+            rendered: AtomicUsize::new(0),
+        }
+    }
+    pub fn total(&self) -> usize {(self.columns * self.rows) as usize}
+    pub fn rendered(&self) -> usize {self.rendered.load(Ordering::Relaxed)}
+    pub fn is_finished(&self) -> bool {self.rendered() >= self.total()}
+    /// Puts every tile back for a new race
+    pub fn reset(&self) {
+        self.next.store(0, Ordering::Relaxed);
+        self.rendered.store(0, Ordering::Relaxed);
+    }
+
+    /// Top left corner of the next tile nobody took yet
+    fn take(&self) -> Option<[i32; 2]> {
+        let index = self.next.fetch_add(1, Ordering::Relaxed);
+        if index >= self.total() { return None }
+        let index = index as i32;
+        Some([index % self.columns * TILE_SIZE, index / self.columns * TILE_SIZE])
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TileSettings {
+    pub fractal: Fractal,
+    /// Shared by all groups, set up by the main controller
+    pub queue: Option<Arc<TileQueue>>,
+}
+
+/// Takes tiles from the queue every group shares and renders the fractal into them,
+/// outlined in the group's color. The race is over once no tile is left
+pub struct TileRender {
+    settings: TileSettings,
+    tile_pos: Option<[i32; 2]>,
+    iterations: [u32; (TILE_SIZE * TILE_SIZE) as usize],
+}
+
+impl TileRender {
+    pub fn new(settings: TileSettings) -> TileRender {
+        TileRender { settings, tile_pos: None, iterations: [0; (TILE_SIZE * TILE_SIZE) as usize] }
+    }
+
+    fn escape_time(z: (f64, f64), c: (f64, f64)) -> u32 {
+        let (mut z_re, mut z_im) = z;
+        for iteration in 0..MAX_ITERATIONS {
+            if z_re * z_re + z_im * z_im > 4. {
+                return iteration;
+            }
+            (z_re, z_im) = (z_re * z_re - z_im * z_im + c.0, 2. * z_re * z_im + c.1);
+        }
+        MAX_ITERATIONS
+    }
+
+    /// Points inside the set are black, the rest gets brighter the faster it escapes
+    fn palette(iterations: u32) -> Color {
+        if iterations >= MAX_ITERATIONS { return Color(0, 0, 0, 255) }
+        let t = (iterations as f32 / MAX_ITERATIONS as f32).sqrt();
+        Color((40. + 215. * t) as u8, (40. + 190. * t) as u8, (60. + 160. * t) as u8, 255)
+    }
+}
+
+impl Workload for TileRender {
+    /// One tile
+    fn work(&mut self, painter: &Painter) -> u64 {
+        let Some(tile_pos) = self.settings.queue.as_ref().and_then(|queue| queue.take()) else {
+            thread::sleep(IDLE_PAUSE);
+            return 0;
+        };
+
+        let (width, height) = (painter.width() as f64, painter.height() as f64);
+        for index in 0..self.iterations.len() {
+            let x = tile_pos[0] + index as i32 % TILE_SIZE;
+            let y = tile_pos[1] + index as i32 / TILE_SIZE;
+            self.iterations[index] = match self.settings.fractal {
+                Fractal::Mandelbrot => TileRender::escape_time((0., 0.), (-2.2 + 3.2 * x as f64 / width, -1.2 + 2.4 * y as f64 / height)),
+                Fractal::Julia => TileRender::escape_time((-1.6 + 3.2 * x as f64 / width, -1.2 + 2.4 * y as f64 / height), JULIA_C),
+            };
+        }
+        self.tile_pos = Some(tile_pos);
+        1
+    }
+
+    fn draw(&mut self, painter: &Painter) {
+        let Some(tile_pos) = self.tile_pos.take() else { return };
+        for (index, iterations) in self.iterations.iter().enumerate() {
+            let (tile_x, tile_y) = (index as i32 % TILE_SIZE, index as i32 / TILE_SIZE);
+            let (x, y) = (tile_pos[0] + tile_x, tile_pos[1] + tile_y);
+            if x >= painter.width() || y >= painter.height() { continue }
+
+            let border = tile_x == 0 || tile_y == 0 || tile_x == TILE_SIZE - 1 || tile_y == TILE_SIZE - 1;
+            let color = if border { painter.color() } else { TileRender::palette(*iterations) };
+            painter.set_color(x, y, color);
+        }
+        if let Some(queue) = &self.settings.queue {
+            queue.rendered.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Keeps the time it takes a group to stop short, tiles take a while
+    fn batch_size(&self) -> u32 { 4 }
+}
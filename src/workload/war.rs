@@ -0,0 +1,86 @@
+use super::dla::FOUR_NEIGHBORS;
+use super::seed::{self, SeedSettings};
+use super::{Painter, Workload, BACKGROUND};
+
+/// Own pixels a worker remembers to attack from
+const MAX_FRONTIER: usize = 4096;
+/// Random picks inside the cluster's bounding box before giving up on finding an own pixel
+const SEARCH_TRIES: u32 = 64;
+
+/// Groups fight over the canvas: every unit picks an own pixel and attacks one of its neighbors.
+/// Background is taken right away, another group's pixel with the probability of the share of its
+/// four neighbors the attacker already owns. Territory keeps shifting with the CPU each group gets
+pub struct TerritoryWar {
+    seed: SeedSettings,
+    /// Pixels the worker conquered, some of them may have been lost again since
+    frontier: Vec<[i32; 2]>,
+    conquered: Option<[i32; 2]>,
+}
+
+impl TerritoryWar {
+    pub fn new(seed: SeedSettings) -> TerritoryWar {
+        TerritoryWar { seed, frontier: Vec::new(), conquered: None }
+    }
+
+    fn is_own(painter: &Painter, pos: [i32; 2]) -> bool {
+        let color = painter.get_color(pos[0], pos[1]);
+        color == painter.color()
+    }
+
+    /// An own pixel to attack from, remembered or found inside the cluster's bounding box
+    fn pick_source(&mut self, painter: &Painter) -> Option<[i32; 2]> {
+        while !self.frontier.is_empty() {
+            let index = rand::random_range(0..self.frontier.len());
+            let pos = self.frontier[index];
+            if TerritoryWar::is_own(painter, pos) { return Some(pos) }
+            self.frontier.swap_remove(index);
+        }
+
+        let (min, max) = painter.bounds().get()?;
+        (0..SEARCH_TRIES)
+            .map(|_| [rand::random_range(min[0]..=max[0]), rand::random_range(min[1]..=max[1])])
+            .find(|pos| TerritoryWar::is_own(painter, *pos))
+    }
+
+    fn remember(&mut self, pos: [i32; 2]) {
+        if self.frontier.len() < MAX_FRONTIER {
+            self.frontier.push(pos);
+        } else {
+            let index = rand::random_range(0..MAX_FRONTIER);
+            self.frontier[index] = pos;
+        }
+    }
+}
+
+impl Workload for TerritoryWar {
+    fn init(&mut self, painter: &Painter) {
+        seed::plant_seeds(painter, &self.seed);
+    }
+
+    /// One attack on a neighbor of an own pixel
+    fn work(&mut self, painter: &Painter) -> u64 {
+        // A group that lost all its pixels is out of the war
+        let Some(source) = self.pick_source(painter) else { return 0 };
+        let (x_bias, y_bias) = FOUR_NEIGHBORS[rand::random_range(0..FOUR_NEIGHBORS.len())];
+        let Some(target) = painter.resolve([source[0] + x_bias, source[1] + y_bias]) else { return 0 };
+        if painter.is_obstacle(target) || TerritoryWar::is_own(painter, target) { return 0 }
+
+        let color = painter.get_color(target[0], target[1]);
+        if color != BACKGROUND {
+            let support = FOUR_NEIGHBORS.iter()
+                .filter(|(x_bias, y_bias)| TerritoryWar::is_own(painter, [target[0] + x_bias, target[1] + y_bias]))
+                .count();
+            if rand::random_range(0..FOUR_NEIGHBORS.len()) >= support { return 0 }
+        }
+        self.conquered = Some(target);
+        1
+    }
+
+    fn draw(&mut self, painter: &Painter) {
+        if let Some(pos) = self.conquered.take() {
+            painter.set_color(pos[0], pos[1], painter.color());
+            painter.bounds().extend(pos);
+            self.remember(pos);
+        }
+    }
+}